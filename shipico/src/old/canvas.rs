@@ -1,6 +1,6 @@
 use std::collections::VecDeque;
 
-use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen::JsCast;
 use web_sys::*;
 
 use crate::math::{Matrix, Point, Rect, Vec2};
@@ -164,18 +164,32 @@ impl Canvas {
         )
     }
 
+    /// Fill text with the baseline starting at the point.
+    pub fn fill_text(&self, text: &str, point: impl Into<Point>, size: f64) {
+        let point = self.transform.transform_point(point);
+        let size = size * self.transform.a;
+        self.render_context
+            .set_font(&format!("{}px sans-serif", size));
+        self.render_context
+            .fill_text(text, point.x, point.y)
+            .unwrap();
+    }
+
     pub fn set_font(&self, font: &str) {
         self.render_context.set_font(font);
     }
 
+    /// Any of the CSS `text-align` values, `"start"` by default.
+    pub fn set_text_align(&self, align: &str) {
+        self.render_context.set_text_align(align);
+    }
+
     pub fn set_fill_style(&self, style: &str) {
-        self.render_context
-            .set_fill_style(&JsValue::from_str(style));
+        self.render_context.set_fill_style_str(style);
     }
 
     pub fn set_stroke_style(&self, style: &str) {
-        self.render_context
-            .set_stroke_style(&JsValue::from_str(style));
+        self.render_context.set_stroke_style_str(style);
     }
 
     pub fn set_line_cap(&self, cap: &str) {
@@ -232,8 +246,7 @@ impl Canvas {
         self.render_context
             .clear_rect(0.0, 0.0, width as f64, height as f64);
         // TODO styles
-        self.render_context
-            .set_fill_style(&JsValue::from_str("#70798c"));
+        self.render_context.set_fill_style_str("#70798c");
         self.render_context
             .fill_rect(0.0, 0.0, width as f64, height as f64);
     }
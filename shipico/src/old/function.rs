@@ -1,155 +1,380 @@
 macro_rules! functions {
-    ($(fn $func_name:ident($($param:ident : $param_type:tt),*) -> ($($ret:ident : $ret_type:tt),*) $body: stmt )*) => {
+    // Return type of the declared function, `Result` wraps it for fallible ones.
+    (@returns Result ($($ret_type:tt),*)) => {
+        Result<($($ret_type),*), String>
+    };
+    (@returns ($($ret_type:tt),*)) => {
+        ($($ret_type),*)
+    };
+    // Result of the function call, infallible functions always succeed.
+    (@result Result $output:expr) => {
+        $output
+    };
+    (@result $output:expr) => {
+        Ok::<_, String>($output)
+    };
+    // Value of the input used when nothing is connected to it.
+    (@default $param_type:tt $default:expr) => {
+        Some(Param::$param_type($default))
+    };
+    (@default $param_type:tt) => {
+        None
+    };
+    ($($(#[doc = $doc:literal])* fn $func_name:ident($($param:ident : $param_type:tt $(= $default:expr)?),*) -> $($result:ident <)? ($($ret:ident : $ret_type:tt),*) $(>)? $body: block)*) => {
         ::paste::paste! {
-            use crate::params::*;
-
-            #[derive(Clone, Debug)]
-            pub struct FunctionDefinition {
-                pub inputs: &'static [ParamType],
-                pub outputs: &'static [ParamType],
-                pub name: &'static str
-            }
-
-            pub const FUNCTIONS: &[FunctionDefinition] = &[
-                $(
-                    FunctionDefinition {
-                        inputs: &[
-                            $(
-                                ParamType::$param_type
-                            ),*
-                        ],
-                        outputs: &[
-                            $(
-                                ParamType::$ret_type
-                            ),*
-                        ],
-                        name: stringify!($func_name),
-                    }
-                ),*
-            ];
-
-            impl FunctionDefinition {
-                fn call(
-                    &self,
-                    input_addresses: &[usize],
-                    memory: &mut Vec<Param>
-                ) -> (usize, usize) {
-                    assert!(self.inputs.len() == input_addresses.len());
-
-                    let output_addresses = (memory.len(), memory.len() + self.outputs.len());
-                    match self.name {
-                        $(
-                            stringify!($func_name) => {
-                                // extracting nessessary inputs from memory
-                                let mut input_index = 0;
-                                $(
-                                    let [<input_ $param>] = memory[input_addresses[input_index]].clone();
-                                    input_index += 1;
-                                )*
-                                let output = $func_name(
-                                    $([<input_ $param>].[<into_ $param_type>]().unwrap()),*
-                                );
-                                let ($([<output_ $ret>]),*) = output;
-
-                                $(
-                                    memory.push(Param::from([<output_ $ret>]));
-                                )*
-                            }
-                        ),*,
-                        _ => unreachable!()
-                    }
-                    output_addresses
-                }
-            }
+            use crate::{function::{Function, FunctionError}, params::*};
+
+            /// Functions declared in this module, in the order of declaration.
+            pub const FUNCTIONS: &[&dyn Function] = &[$(&[<$func_name:camel>]),*];
 
             $(
-                #[allow(unused_parens)]
-                pub fn $func_name($($param:$param_type),*) -> ($($ret_type),*) {
+                $(#[doc = $doc])*
+                #[allow(unused_parens, unused_variables)]
+                pub fn $func_name($($param:$param_type),*) -> functions!(@returns $($result)? ($($ret_type),*)) {
                     $body
                 }
+
+                /// Node type calling the function of the same name.
+                pub struct [<$func_name:camel>];
+
+                impl Function for [<$func_name:camel>] {
+                    fn name(&self) -> &'static str {
+                        stringify!($func_name)
+                    }
+
+                    fn description(&self) -> &'static str {
+                        concat!($($doc, "\n"),*)
+                    }
+
+                    fn inputs(&self) -> &'static [ParamType] {
+                        &[$(ParamType::$param_type),*]
+                    }
+
+                    fn outputs(&self) -> &'static [ParamType] {
+                        &[$(ParamType::$ret_type),*]
+                    }
+
+                    fn input_names(&self) -> &'static [&'static str] {
+                        &[$(stringify!($param)),*]
+                    }
+
+                    fn output_names(&self) -> &'static [&'static str] {
+                        &[$(stringify!($ret)),*]
+                    }
+
+                    fn defaults(&self) -> &'static [Option<Param>] {
+                        const DEFAULTS: &[Option<Param>] = &[
+                            $(functions!(@default $param_type $($default)?)),*
+                        ];
+                        DEFAULTS
+                    }
+
+                    #[allow(unused_mut, unused_variables, unused_assignments, unused_parens)]
+                    fn call(
+                        &self,
+                        input_addresses: &[usize],
+                        memory: &mut Vec<Param>
+                    ) -> Result<(usize, usize), FunctionError> {
+                        let output_addresses = (memory.len(), memory.len() + self.outputs().len());
+                        // extracting nessessary inputs from memory
+                        let mut input_index = 0;
+                        $(
+                            let [<input_ $param>] = memory
+                                .get(input_addresses[input_index])
+                                .cloned()
+                                .and_then(Param::[<into_ $param_type>])
+                                .ok_or_else(|| FunctionError::wrong_input(
+                                    input_index,
+                                    ParamType::$param_type,
+                                ))?;
+                            input_index += 1;
+                        )*
+                        let output = functions!(@result $($result)? $func_name(
+                            $([<input_ $param>]),*
+                        ));
+                        let ($([<output_ $ret>]),*) = output.map_err(|message| FunctionError {
+                            input: None,
+                            message,
+                        })?;
+
+                        $(
+                            memory.push(Param::from([<output_ $ret>]));
+                        )*
+                        Ok(output_addresses)
+                    }
+                }
             )*
         }
     };
 }
 
-// trait Function {
-//     // const INPUT: &'static [ParamType];
-//     // const OUTPUT: &'static [ParamType];
-//     // This is required to be as function
-//     // I can't just use associated consts,
-//     // because this way it would not be possible
-//     // to use Function trait as trait object.
-//     fn function_inputs(&self) -> &'static [ParamType];
-//     fn function_outputs(&self) -> &'static [ParamType];
-//     fn exec(&self, input_addresses: &[usize], memory: &mut Vec<Param>) -> (usize, usize);
-// }
-
-//     fn exec(&self, input_addresses: &[usize], memory: &mut Stack) -> Vec<usize> {
-//         assert_eq!(input_addresses.len(), Self::INPUT.len());
-
-//         let mut output_addresses = Vec::with_capacity(Self::OUTPUT.len());
-
-//         let mut inputs = vec![];
-//         for (i, addr) in input_addresses.iter().enumerate() {
-//             let input = memory._inner[*addr].clone();
-//             assert_eq!(std::mem::discriminant(&input), Self::INPUT[i]);
-//             inputs.push(input);
-//         }
-
-//         let param = &memory._inner[input_addresses[0]];
-
-//         output_addresses.push(memory._inner.len());
-//         let output = (self)(inputs[0].into_i32().unwrap());
-//         memory._inner.push(Param::from_f32(output));
-
-//         output_addresses
-//     }
-
-functions!(
-    fn asd(param1: i64, param2: f32) -> (
-        result: f32
-    ) {
-        return (param2);
+pub mod arithmetic;
+pub mod comparison;
+pub mod constants;
+pub mod logic;
+pub mod range;
+mod registry;
+pub mod rounding;
+pub mod trigonometry;
+
+use crate::params::{Param, ParamType};
+
+pub use registry::{FunctionRegistry, RegistryError};
+
+/// Node type, what function node computes.
+///
+/// Functions declared with `functions!` implement it, other modules and
+/// crates can implement it by hand and add their functions to the
+/// [`FunctionRegistry`] at startup.
+pub trait Function: Send + Sync {
+    /// Name of the function, unique within its namespace.
+    fn name(&self) -> &'static str;
+
+    /// What function does, shown to the user.
+    fn description(&self) -> &'static str {
+        ""
+    }
+
+    fn inputs(&self) -> &'static [ParamType];
+
+    fn outputs(&self) -> &'static [ParamType];
+
+    /// Parameter names, in the order of `inputs`.
+    fn input_names(&self) -> &'static [&'static str];
+
+    /// Return value names, in the order of `outputs`.
+    fn output_names(&self) -> &'static [&'static str];
+
+    /// Values of the inputs nothing is connected to, in the order of
+    /// `inputs`. Inputs without the value, or past the end, are required.
+    fn defaults(&self) -> &'static [Option<Param>] {
+        &[]
+    }
+
+    /// Calls the function with the inputs read from the memory at
+    /// `input_addresses`, pushes the outputs to the memory and returns the
+    /// range of their addresses.
+    ///
+    /// Nothing is pushed if the function fails or an input has a value of
+    /// the wrong type. Number of the addresses is checked by the caller.
+    fn call(
+        &self,
+        input_addresses: &[usize],
+        memory: &mut Vec<Param>,
+    ) -> Result<(usize, usize), FunctionError>;
+}
+
+/// Function registered in the [`FunctionRegistry`].
+#[derive(Clone)]
+pub struct FunctionDefinition {
+    /// Unique name, namespace and name of the function joined with a dot,
+    /// like `std.boolean`.
+    pub name: &'static str,
+    /// Name of the function without the namespace, shown on the nodes.
+    pub title: &'static str,
+    /// Group the function is listed in.
+    pub category: &'static str,
+    pub description: &'static str,
+    pub inputs: &'static [ParamType],
+    pub outputs: &'static [ParamType],
+    /// Parameter names, in the order of `inputs`.
+    pub input_names: &'static [&'static str],
+    /// Return value names, in the order of `outputs`.
+    pub output_names: &'static [&'static str],
+    /// Values of the optional inputs, see [`Function::defaults`].
+    pub defaults: &'static [Option<Param>],
+    function: &'static dyn Function,
+}
+
+impl std::fmt::Debug for FunctionDefinition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("FunctionDefinition")
+            .field("name", &self.name)
+            .field("inputs", &self.inputs)
+            .field("outputs", &self.outputs)
+            .finish()
+    }
+}
+
+/// Why function could not compute its outputs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionError {
+    /// Index of the input with the wrong value, `None` if the function
+    /// itself failed.
+    pub input: Option<usize>,
+    pub message: String,
+}
+
+impl FunctionError {
+    /// Error for the input at `input` that has no value of the `expected` type.
+    pub fn wrong_input(input: usize, expected: ParamType) -> FunctionError {
+        FunctionError {
+            input: Some(input),
+            message: format!("input #{} is not `{}`", input, expected),
+        }
+    }
+}
+
+impl std::fmt::Display for FunctionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for FunctionError {}
+
+/// Placeholder functions.
+pub mod misc {
+    functions!(
+        fn asd(param1: i64, param2: f32) -> (
+            result: f32
+        ) {
+            return (param2);
+        }
+
+        fn foo(param3: f64) -> () {
+
+        }
+    );
+}
+
+/// Placeholder geometry nodes, until the real geometry lands.
+pub mod geometry {
+    functions!(
+        /// Geometry the tree starts with.
+        fn input_geo() -> (
+            geometry: f64
+        ) {
+            return (0.0);
+        }
+
+        /// Boolean operation on two geometries.
+        fn boolean(left: f64, right: f64) -> (
+            result: f64
+        ) {
+            return (left);
+        }
+
+        /// Geometry the tree computes.
+        fn output_geo(geometry: f64) -> () {
+
+        }
+    );
+}
+
+/// Conversions between the number types, see [`CONVERSIONS`].
+pub mod conversion {
+    functions!(
+        /// Converts integer to the float, rounding the large values.
+        fn i64_to_f64(value: i64) -> (
+            result: f64
+        ) {
+            return (value as f64);
+        }
+
+        /// Converts float to the double precision float.
+        fn f32_to_f64(value: f32) -> (
+            result: f64
+        ) {
+            return (value as f64);
+        }
+    );
+}
+
+/// Namespace of the functions declared in this crate.
+pub const STD: &str = "std";
+
+/// Conversions that are safe to insert between sockets of different types
+/// without asking user: `(from, to, function name)`.
+const CONVERSIONS: &[(ParamType, ParamType, &str)] = &[
+    (ParamType::i64, ParamType::f64, "std.i64_to_f64"),
+    (ParamType::f32, ParamType::f64, "std.f32_to_f64"),
+];
+
+impl FunctionDefinition {
+    /// Definition of the `function` registered under the namespaced `name`.
+    fn new(name: &'static str, category: &'static str, function: &'static dyn Function) -> Self {
+        FunctionDefinition {
+            name,
+            title: function.name(),
+            category,
+            description: function.description().trim(),
+            inputs: function.inputs(),
+            outputs: function.outputs(),
+            input_names: function.input_names(),
+            output_names: function.output_names(),
+            defaults: function.defaults(),
+            function,
+        }
+    }
+
+    /// Looks up function in the global [`FunctionRegistry`], see
+    /// [`FunctionRegistry::find`].
+    pub fn find(name: &str) -> Option<&'static FunctionDefinition> {
+        FunctionRegistry::global().find(name)
+    }
+
+    /// Function converting `from` type to `to` type, if such conversion is safe.
+    pub fn conversion(from: ParamType, to: ParamType) -> Option<&'static FunctionDefinition> {
+        CONVERSIONS
+            .iter()
+            .find(|(x, y, _)| *x == from && *y == to)
+            .and_then(|(_, _, name)| Self::find(name))
     }
 
-    fn foo(param3: f64) -> () {
+    /// Value the input gets when nothing is connected to it, `None` if the
+    /// input is required.
+    pub fn default(&self, index: usize) -> Option<&'static Param> {
+        self.defaults.get(index).and_then(Option::as_ref)
+    }
 
+    /// Calls the function with the inputs read from the memory at
+    /// `input_addresses`, pushes the outputs to the memory and returns the
+    /// range of their addresses, see [`Function::call`].
+    pub fn call(
+        &self,
+        input_addresses: &[usize],
+        memory: &mut Vec<Param>,
+    ) -> Result<(usize, usize), FunctionError> {
+        if self.inputs.len() != input_addresses.len() {
+            return Err(FunctionError {
+                input: None,
+                message: format!(
+                    "expected {} inputs, got {}",
+                    self.inputs.len(),
+                    input_addresses.len()
+                ),
+            });
+        }
+        self.function.call(input_addresses, memory)
     }
-);
+}
 
 pub fn aaaa() {
-    asd(5, 2.0);
+    misc::asd(5, 2.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calls the function `name` of the `std` namespace with the `inputs`
+    /// and returns its outputs.
+    pub(super) fn call(name: &str, inputs: &[Param]) -> Result<Vec<Param>, FunctionError> {
+        let function = FunctionDefinition::find(&format!("{}.{}", STD, name)).unwrap();
+        let mut memory = inputs.to_vec();
+        let addresses = (0..inputs.len()).collect::<Vec<_>>();
+        let (start, end) = function.call(&addresses, &mut memory)?;
+        assert_eq!(end - start, function.outputs.len());
+        Ok(memory[start..end].to_vec())
+    }
+
+    #[test]
+    fn wrong_inputs() {
+        assert_eq!(call("add", &[Param::f64(1.0)]).unwrap_err().input, None);
+        assert_eq!(
+            call("add", &[Param::f64(1.0), Param::i64(2)]),
+            Err(FunctionError::wrong_input(1, ParamType::f64))
+        );
+    }
 }
-// trait Function {
-//     const INPUT_SIZE: usize;
-//     const OUTPUT_SIZE: usize;
-//     const INPUTS: [ParamType; Self::INPUT_SIZE];
-//     const OUTPUTS: [ParamType; Self::OUTPUT_SIZE];
-
-//     fn exec(
-//         &self,
-//         input_addresses: [usize; Self::INPUT_SIZE],
-//         memory: &mut Vec<Param>,
-//     ) -> (usize, usize);
-// }
-
-// struct A {}
-
-// impl Function for A {
-//     const INPUT_SIZE: usize = 1;
-
-//     const OUTPUT_SIZE: usize = 1;
-
-//     const INPUTS: [ParamType; Self::INPUT_SIZE] = [ParamType::i64];
-
-//     const OUTPUTS: [ParamType; Self::OUTPUT_SIZE] = [ParamType::i64];
-
-//     fn exec(
-//         &self,
-//         input_addresses: [usize; Self::INPUT_SIZE],
-//         memory: &mut Vec<Param>,
-//     ) -> (usize, usize) {
-//         (0, 0)
-//     }
-// }
-//
@@ -0,0 +1,100 @@
+//! Arithmetic on numbers.
+
+functions!(
+    /// Sum of `left` and `right`.
+    fn add(left: f64, right: f64) -> (
+        result: f64
+    ) {
+        left + right
+    }
+
+    /// Difference of `left` and `right`.
+    fn subtract(left: f64, right: f64) -> (
+        result: f64
+    ) {
+        left - right
+    }
+
+    /// Product of `left` and `right`.
+    fn multiply(left: f64, right: f64) -> (
+        result: f64
+    ) {
+        left * right
+    }
+
+    /// Divides `left` by `right`, fails if `right` is zero.
+    fn divide(left: f64, right: f64) -> Result<(
+        result: f64
+    )> {
+        if right == 0.0 {
+            return Err("division by zero".to_string());
+        }
+        Ok(left / right)
+    }
+
+    /// Remainder of dividing `left` by `right`, never negative, fails if
+    /// `right` is zero.
+    fn modulo(left: f64, right: f64) -> Result<(
+        result: f64
+    )> {
+        if right == 0.0 {
+            return Err("division by zero".to_string());
+        }
+        Ok(left.rem_euclid(right))
+    }
+
+    /// `base` raised to the power of `exponent`.
+    fn power(base: f64, exponent: f64 = 2.0) -> (
+        result: f64
+    ) {
+        base.powf(exponent)
+    }
+
+    /// Square root of `value`, fails if `value` is negative.
+    fn sqrt(value: f64) -> Result<(
+        result: f64
+    )> {
+        if value < 0.0 {
+            return Err(format!("square root of negative {}", value));
+        }
+        Ok(value.sqrt())
+    }
+
+    /// Opposite of `value`.
+    fn negate(value: f64) -> (
+        result: f64
+    ) {
+        -value
+    }
+
+    /// Absolute value of `value`.
+    fn abs(value: f64) -> (
+        result: f64
+    ) {
+        value.abs()
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::function::tests::call;
+
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let f = Param::f64;
+        assert_eq!(call("add", &[f(2.0), f(3.0)]), Ok(vec![f(5.0)]));
+        assert_eq!(call("subtract", &[f(2.0), f(3.0)]), Ok(vec![f(-1.0)]));
+        assert_eq!(call("multiply", &[f(2.0), f(3.0)]), Ok(vec![f(6.0)]));
+        assert_eq!(call("divide", &[f(3.0), f(2.0)]), Ok(vec![f(1.5)]));
+        assert!(call("divide", &[f(3.0), f(0.0)]).is_err());
+        assert_eq!(call("modulo", &[f(-1.0), f(3.0)]), Ok(vec![f(2.0)]));
+        assert!(call("modulo", &[f(1.0), f(0.0)]).is_err());
+        assert_eq!(call("power", &[f(2.0), f(10.0)]), Ok(vec![f(1024.0)]));
+        assert_eq!(call("sqrt", &[f(9.0)]), Ok(vec![f(3.0)]));
+        assert!(call("sqrt", &[f(-9.0)]).is_err());
+        assert_eq!(call("negate", &[f(2.0)]), Ok(vec![f(-2.0)]));
+        assert_eq!(call("abs", &[f(-2.0)]), Ok(vec![f(2.0)]));
+    }
+}
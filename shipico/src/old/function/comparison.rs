@@ -0,0 +1,85 @@
+//! Comparisons of numbers.
+
+functions!(
+    /// Whether `left` and `right` are exactly the same number.
+    fn equal(left: f64, right: f64) -> (
+        result: bool
+    ) {
+        left == right
+    }
+
+    /// Whether `left` and `right` are different numbers.
+    fn not_equal(left: f64, right: f64) -> (
+        result: bool
+    ) {
+        left != right
+    }
+
+    /// Whether `left` and `right` differ by no more than `tolerance`.
+    fn nearly_equal(left: f64, right: f64, tolerance: f64 = 1e-9) -> (
+        result: bool
+    ) {
+        (left - right).abs() <= tolerance
+    }
+
+    /// Whether `left` is smaller than `right`.
+    fn less(left: f64, right: f64) -> (
+        result: bool
+    ) {
+        left < right
+    }
+
+    /// Whether `left` is smaller than `right` or equal to it.
+    fn less_equal(left: f64, right: f64) -> (
+        result: bool
+    ) {
+        left <= right
+    }
+
+    /// Whether `left` is bigger than `right`.
+    fn greater(left: f64, right: f64) -> (
+        result: bool
+    ) {
+        left > right
+    }
+
+    /// Whether `left` is bigger than `right` or equal to it.
+    fn greater_equal(left: f64, right: f64) -> (
+        result: bool
+    ) {
+        left >= right
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::function::tests::call;
+
+    use super::*;
+
+    #[test]
+    fn comparison() {
+        let compare = |name, left, right| {
+            call(name, &[Param::f64(left), Param::f64(right)]).unwrap()[0]
+                .clone()
+                .into_bool()
+                .unwrap()
+        };
+        assert!(compare("equal", 1.0, 1.0));
+        assert!(!compare("equal", 1.0, 2.0));
+        assert!(compare("not_equal", 1.0, 2.0));
+        assert!(compare("less", 1.0, 2.0));
+        assert!(!compare("less", 2.0, 2.0));
+        assert!(compare("less_equal", 2.0, 2.0));
+        assert!(compare("greater", 3.0, 2.0));
+        assert!(!compare("greater", 2.0, 2.0));
+        assert!(compare("greater_equal", 2.0, 2.0));
+
+        let nearly = |left, right| {
+            let inputs = [Param::f64(left), Param::f64(right), Param::f64(0.01)];
+            call("nearly_equal", &inputs).unwrap()[0] == Param::bool(true)
+        };
+        assert!(nearly(0.1 + 0.2, 0.3));
+        assert!(!nearly(0.1, 0.3));
+    }
+}
@@ -0,0 +1,48 @@
+//! Mathematical constants.
+
+functions!(
+    /// Ratio of the circumference of a circle to its diameter.
+    fn pi() -> (
+        value: f64
+    ) {
+        std::f64::consts::PI
+    }
+
+    /// Full turn in radians, twice the `pi`.
+    fn tau() -> (
+        value: f64
+    ) {
+        std::f64::consts::TAU
+    }
+
+    /// Base of the natural logarithm.
+    fn e() -> (
+        value: f64
+    ) {
+        std::f64::consts::E
+    }
+
+    /// Square root of two.
+    fn sqrt_2() -> (
+        value: f64
+    ) {
+        std::f64::consts::SQRT_2
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts;
+
+    use crate::function::tests::call;
+
+    use super::*;
+
+    #[test]
+    fn constants() {
+        assert_eq!(call("pi", &[]), Ok(vec![Param::f64(consts::PI)]));
+        assert_eq!(call("tau", &[]), Ok(vec![Param::f64(consts::TAU)]));
+        assert_eq!(call("e", &[]), Ok(vec![Param::f64(consts::E)]));
+        assert_eq!(call("sqrt_2", &[]), Ok(vec![Param::f64(consts::SQRT_2)]));
+    }
+}
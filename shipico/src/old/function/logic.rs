@@ -0,0 +1,71 @@
+//! Boolean logic.
+
+functions!(
+    /// Whether both `left` and `right` are true.
+    fn and(left: bool, right: bool) -> (
+        result: bool
+    ) {
+        left && right
+    }
+
+    /// Whether at least one of `left` and `right` is true.
+    fn or(left: bool, right: bool) -> (
+        result: bool
+    ) {
+        left || right
+    }
+
+    /// Whether exactly one of `left` and `right` is true.
+    fn xor(left: bool, right: bool) -> (
+        result: bool
+    ) {
+        left != right
+    }
+
+    /// Opposite of `value`.
+    fn not(value: bool) -> (
+        result: bool
+    ) {
+        !value
+    }
+
+    /// `if_true` if the `condition` holds, `if_false` otherwise.
+    fn select(condition: bool, if_true: f64, if_false: f64) -> (
+        result: f64
+    ) {
+        if condition {
+            if_true
+        } else {
+            if_false
+        }
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::function::tests::call;
+
+    use super::*;
+
+    #[test]
+    fn logic() {
+        let b = Param::bool;
+        let table = |name| {
+            [(false, false), (false, true), (true, false), (true, true)]
+                .iter()
+                .map(|&(left, right)| call(name, &[b(left), b(right)]).unwrap()[0] == b(true))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(table("and"), [false, false, false, true]);
+        assert_eq!(table("or"), [false, true, true, true]);
+        assert_eq!(table("xor"), [false, true, true, false]);
+        assert_eq!(call("not", &[b(true)]), Ok(vec![b(false)]));
+
+        let f = Param::f64;
+        assert_eq!(call("select", &[b(true), f(1.0), f(2.0)]), Ok(vec![f(1.0)]));
+        assert_eq!(
+            call("select", &[b(false), f(1.0), f(2.0)]),
+            Ok(vec![f(2.0)])
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! Picking and blending numbers within the range.
+
+functions!(
+    /// Smaller of `left` and `right`.
+    fn min(left: f64, right: f64) -> (
+        result: f64
+    ) {
+        left.min(right)
+    }
+
+    /// Larger of `left` and `right`.
+    fn max(left: f64, right: f64) -> (
+        result: f64
+    ) {
+        left.max(right)
+    }
+
+    /// Restricts `value` to the `min..=max` range, fails if `min` is larger
+    /// than `max`. Range is `0..=1` unless set.
+    fn clamp(value: f64, min: f64 = 0.0, max: f64 = 1.0) -> Result<(
+        result: f64
+    )> {
+        if min > max || min.is_nan() || max.is_nan() {
+            return Err(format!("range {}..={} is empty", min, max));
+        }
+        Ok(value.clamp(min, max))
+    }
+
+    /// Value between `from` and `to` at `factor`, `from` at 0 and `to` at 1.
+    fn lerp(from: f64, to: f64, factor: f64 = 0.5) -> (
+        result: f64
+    ) {
+        from + (to - from) * factor
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::function::{tests::call, FunctionDefinition};
+
+    use super::*;
+
+    #[test]
+    fn range() {
+        let f = Param::f64;
+        assert_eq!(call("min", &[f(2.0), f(3.0)]), Ok(vec![f(2.0)]));
+        assert_eq!(call("max", &[f(2.0), f(3.0)]), Ok(vec![f(3.0)]));
+        assert_eq!(call("clamp", &[f(5.0), f(0.0), f(1.0)]), Ok(vec![f(1.0)]));
+        assert_eq!(call("clamp", &[f(-5.0), f(0.0), f(1.0)]), Ok(vec![f(0.0)]));
+        assert!(call("clamp", &[f(0.5), f(1.0), f(0.0)]).is_err());
+        assert!(call("clamp", &[f(0.5), f(f64::NAN), f(0.0)]).is_err());
+        assert_eq!(call("lerp", &[f(2.0), f(4.0), f(0.25)]), Ok(vec![f(2.5)]));
+        assert_eq!(call("lerp", &[f(2.0), f(4.0), f(2.0)]), Ok(vec![f(6.0)]));
+
+        let clamp = FunctionDefinition::find("std.clamp").unwrap();
+        assert_eq!(clamp.default(0), None);
+        assert_eq!(clamp.default(2), Some(&f(1.0)));
+    }
+}
@@ -0,0 +1,241 @@
+//! Functions available to the trees, collected at startup.
+//!
+//! Every function is registered in a namespace, so modules and crates can
+//! declare functions with the same names. Functions of this crate are in
+//! the [`STD`] namespace of the global registry from the start, others are
+//! added with [`FunctionRegistry::global_mut`] before the first tree is
+//! created.
+
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use once_cell::sync::Lazy;
+
+use super::{
+    arithmetic, comparison, constants, conversion, geometry, logic, misc, range, rounding,
+    trigonometry, Function, FunctionDefinition, STD,
+};
+
+static REGISTRY: Lazy<RwLock<FunctionRegistry>> =
+    Lazy::new(|| RwLock::new(FunctionRegistry::with_std()));
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistryError {
+    /// Function with the same name is already registered in the namespace.
+    Duplicate { name: String },
+    /// Namespace is empty or has a dot in it.
+    InvalidNamespace { namespace: String },
+}
+
+impl std::fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RegistryError::Duplicate { name } => {
+                write!(f, "function `{}` is already registered", name)
+            }
+            RegistryError::InvalidNamespace { namespace } => {
+                write!(f, "invalid namespace `{}`", namespace)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// Functions nodes can compute, in the order they were registered.
+#[derive(Default)]
+pub struct FunctionRegistry {
+    functions: Vec<&'static FunctionDefinition>,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with the functions of this crate.
+    pub fn with_std() -> Self {
+        let mut registry = Self::new();
+        let modules: &[(&str, &[&dyn Function])] = &[
+            ("geometry", geometry::FUNCTIONS),
+            ("arithmetic", arithmetic::FUNCTIONS),
+            ("trigonometry", trigonometry::FUNCTIONS),
+            ("range", range::FUNCTIONS),
+            ("rounding", rounding::FUNCTIONS),
+            ("comparison", comparison::FUNCTIONS),
+            ("logic", logic::FUNCTIONS),
+            ("constants", constants::FUNCTIONS),
+            ("conversion", conversion::FUNCTIONS),
+            ("misc", misc::FUNCTIONS),
+        ];
+        for (category, functions) in modules {
+            registry
+                .register(STD, category, functions)
+                .expect("std functions have unique names");
+        }
+        registry
+    }
+
+    /// Registry every tree resolves its functions through.
+    pub fn global() -> RwLockReadGuard<'static, FunctionRegistry> {
+        REGISTRY.read().unwrap()
+    }
+
+    pub fn global_mut() -> RwLockWriteGuard<'static, FunctionRegistry> {
+        REGISTRY.write().unwrap()
+    }
+
+    /// Adds `functions` to the `namespace`, listed under the `category`.
+    ///
+    /// Nothing is added if any of the names is taken.
+    pub fn register(
+        &mut self,
+        namespace: &str,
+        category: &'static str,
+        functions: &[&'static dyn Function],
+    ) -> Result<(), RegistryError> {
+        if namespace.is_empty() || namespace.contains('.') {
+            return Err(RegistryError::InvalidNamespace {
+                namespace: namespace.to_string(),
+            });
+        }
+        let names = functions
+            .iter()
+            .map(|function| format!("{}.{}", namespace, function.name()))
+            .collect::<Vec<_>>();
+        for (index, name) in names.iter().enumerate() {
+            if self.find_exact(name).is_some() || names[..index].contains(name) {
+                return Err(RegistryError::Duplicate { name: name.clone() });
+            }
+        }
+        // Definitions live as long as the program, so nodes keep plain
+        // references to them. Leaked only once the whole batch is accepted.
+        self.functions
+            .extend(names.into_iter().zip(functions).map(|(name, &function)| {
+                let name = Box::leak(name.into_boxed_str());
+                &*Box::leak(Box::new(FunctionDefinition::new(name, category, function)))
+            }));
+        Ok(())
+    }
+
+    /// Looks up function by its namespaced name, like `std.boolean`.
+    ///
+    /// Name without the namespace resolves to the first registered function
+    /// with such name, documents saved before the namespaces refer to the
+    /// functions this way.
+    pub fn find(&self, name: &str) -> Option<&'static FunctionDefinition> {
+        if name.contains('.') {
+            self.find_exact(name)
+        } else {
+            self.functions.iter().copied().find(|x| x.title == name)
+        }
+    }
+
+    fn find_exact(&self, name: &str) -> Option<&'static FunctionDefinition> {
+        self.functions.iter().copied().find(|x| x.name == name)
+    }
+
+    /// All registered functions, in the order they were registered.
+    pub fn functions(&self) -> &[&'static FunctionDefinition] {
+        &self.functions
+    }
+
+    /// Names of the categories, in the order their first functions were
+    /// registered.
+    pub fn categories(&self) -> Vec<&'static str> {
+        let mut categories = Vec::new();
+        for function in &self.functions {
+            if !categories.contains(&function.category) {
+                categories.push(function.category);
+            }
+        }
+        categories
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        function::FunctionError,
+        params::{Param, ParamType},
+    };
+
+    use super::*;
+
+    /// Function implemented outside of `functions!`, like other crates do.
+    struct Negate;
+
+    impl Function for Negate {
+        fn name(&self) -> &'static str {
+            "boolean"
+        }
+
+        fn inputs(&self) -> &'static [ParamType] {
+            &[ParamType::f64]
+        }
+
+        fn outputs(&self) -> &'static [ParamType] {
+            &[ParamType::f64]
+        }
+
+        fn input_names(&self) -> &'static [&'static str] {
+            &["value"]
+        }
+
+        fn output_names(&self) -> &'static [&'static str] {
+            &["result"]
+        }
+
+        fn call(
+            &self,
+            input_addresses: &[usize],
+            memory: &mut Vec<Param>,
+        ) -> Result<(usize, usize), FunctionError> {
+            let value = memory[input_addresses[0]]
+                .clone()
+                .into_f64()
+                .ok_or_else(|| FunctionError::wrong_input(0, ParamType::f64))?;
+            memory.push(Param::f64(-value));
+            Ok((memory.len() - 1, memory.len()))
+        }
+    }
+
+    #[test]
+    fn registry() {
+        let mut registry = FunctionRegistry::with_std();
+        registry.register("plugin", "custom", &[&Negate]).unwrap();
+        assert_eq!(
+            registry.register("plugin", "custom", &[&Negate]),
+            Err(RegistryError::Duplicate {
+                name: "plugin.boolean".to_string()
+            })
+        );
+        assert!(registry.register("", "custom", &[&Negate]).is_err());
+
+        // Same names live in different namespaces, bare names find the
+        // first one.
+        let plugin = registry.find("plugin.boolean").unwrap();
+        assert_eq!((plugin.title, plugin.category), ("boolean", "custom"));
+        assert_eq!(registry.find("boolean").unwrap().name, "std.boolean");
+        assert_eq!(registry.categories().last(), Some(&"custom"));
+
+        let mut memory = vec![Param::f64(2.0)];
+        assert_eq!(plugin.call(&[0], &mut memory), Ok((1, 2)));
+        assert_eq!(memory[1], Param::f64(-2.0));
+
+        // Menu shows what every function of the standard library does.
+        let library = [
+            "arithmetic",
+            "trigonometry",
+            "range",
+            "rounding",
+            "comparison",
+            "logic",
+            "constants",
+        ];
+        for function in registry.functions() {
+            if library.contains(&function.category) {
+                assert_ne!(function.description, "", "{}", function.name);
+            }
+        }
+    }
+}
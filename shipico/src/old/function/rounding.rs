@@ -0,0 +1,66 @@
+//! Rounding numbers to the integers.
+
+functions!(
+    /// Largest integer not greater than `value`.
+    fn floor(value: f64) -> (
+        result: f64
+    ) {
+        value.floor()
+    }
+
+    /// Smallest integer not less than `value`.
+    fn ceil(value: f64) -> (
+        result: f64
+    ) {
+        value.ceil()
+    }
+
+    /// Nearest integer to `value`, halves are rounded away from zero.
+    fn round(value: f64) -> (
+        result: f64
+    ) {
+        value.round()
+    }
+
+    /// Integer part of `value`, rounded towards zero.
+    fn trunc(value: f64) -> (
+        result: f64
+    ) {
+        value.trunc()
+    }
+
+    /// Part of `value` after the point, with the sign of `value`.
+    fn fract(value: f64) -> (
+        result: f64
+    ) {
+        value.fract()
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::function::tests::call;
+
+    use super::*;
+
+    #[test]
+    fn rounding() {
+        let round = |name, value| {
+            let values = [-1.5, value];
+            values
+                .iter()
+                .map(|&x| {
+                    call(name, &[Param::f64(x)]).unwrap()[0]
+                        .clone()
+                        .into_f64()
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(round("floor", 2.5), [-2.0, 2.0]);
+        assert_eq!(round("ceil", 2.5), [-1.0, 3.0]);
+        assert_eq!(round("round", 2.5), [-2.0, 3.0]);
+        assert_eq!(round("trunc", 2.5), [-1.0, 2.0]);
+        assert_eq!(round("fract", 2.25), [-0.5, 0.25]);
+    }
+}
@@ -0,0 +1,106 @@
+//! Trigonometric functions, angles are in radians.
+
+functions!(
+    /// Sine of the `angle`.
+    fn sin(angle: f64) -> (
+        result: f64
+    ) {
+        angle.sin()
+    }
+
+    /// Cosine of the `angle`.
+    fn cos(angle: f64) -> (
+        result: f64
+    ) {
+        angle.cos()
+    }
+
+    /// Tangent of the `angle`.
+    fn tan(angle: f64) -> (
+        result: f64
+    ) {
+        angle.tan()
+    }
+
+    /// Angle with the sine `value`, fails if `value` is not in `-1..=1`.
+    fn asin(value: f64) -> Result<(
+        angle: f64
+    )> {
+        if !(-1.0..=1.0).contains(&value) {
+            return Err(format!("arcsine of {} is undefined", value));
+        }
+        Ok(value.asin())
+    }
+
+    /// Angle with the cosine `value`, fails if `value` is not in `-1..=1`.
+    fn acos(value: f64) -> Result<(
+        angle: f64
+    )> {
+        if !(-1.0..=1.0).contains(&value) {
+            return Err(format!("arccosine of {} is undefined", value));
+        }
+        Ok(value.acos())
+    }
+
+    /// Angle with the tangent `value`, between `-pi/2` and `pi/2`.
+    fn atan(value: f64) -> (
+        angle: f64
+    ) {
+        value.atan()
+    }
+
+    /// Angle between the x axis and the point `(x, y)`.
+    fn atan2(y: f64, x: f64) -> (
+        angle: f64
+    ) {
+        y.atan2(x)
+    }
+
+    /// Angle in radians converted to degrees.
+    fn to_degrees(radians: f64) -> (
+        degrees: f64
+    ) {
+        radians.to_degrees()
+    }
+
+    /// Angle in degrees converted to radians.
+    fn to_radians(degrees: f64) -> (
+        radians: f64
+    ) {
+        degrees.to_radians()
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    use crate::function::tests::call;
+
+    use super::*;
+
+    /// Calls the function with one number and returns the number it gives.
+    fn unary(name: &str, value: f64) -> f64 {
+        call(name, &[Param::f64(value)]).unwrap()[0]
+            .clone()
+            .into_f64()
+            .unwrap()
+    }
+
+    #[test]
+    fn trigonometry() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(close(unary("sin", FRAC_PI_2), 1.0));
+        assert!(close(unary("cos", PI), -1.0));
+        assert!(close(unary("tan", FRAC_PI_4), 1.0));
+        assert!(close(unary("asin", 1.0), FRAC_PI_2));
+        assert!(close(unary("acos", 1.0), 0.0));
+        assert!(close(unary("atan", 1.0), FRAC_PI_4));
+        assert!(call("asin", &[Param::f64(2.0)]).is_err());
+        assert!(call("acos", &[Param::f64(-2.0)]).is_err());
+        let angle = call("atan2", &[Param::f64(1.0), Param::f64(-1.0)]).unwrap();
+        assert!(close(angle[0].clone().into_f64().unwrap(), 3.0 * FRAC_PI_4));
+        assert!(close(unary("to_degrees", PI), 180.0));
+        assert!(close(unary("to_radians", 90.0), FRAC_PI_2));
+    }
+}
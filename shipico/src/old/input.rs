@@ -9,11 +9,13 @@ use crate::{
 #[derive(Debug, Clone)]
 pub enum InputMouseEvent {
     Click(Point),
+    DoubleClick(Point),
 
     StartDrag(Point, Vec2),
     Drag(Point, Vec2),
     EndDrag(Point),
 
+    #[allow(dead_code)]
     Wheel(f64),
 
     None,
@@ -38,9 +40,23 @@ bitflags::bitflags! {
         const SHIFT =       0b0000_0000_0100_0000;
         const CTRL =        0b0000_0000_1000_0000;
         const ALT =         0b0000_0001_0000_0000;
+        const SAVE =        0b0000_0010_0000_0000;
+        const UNDO =        0b0000_0100_0000_0000;
+        const DUPLICATE =   0b0000_1000_0000_0000;
+        const REMOVE =      0b0001_0000_0000_0000;
+        const GROUP =       0b0010_0000_0000_0000;
+        const BACK =        0b0100_0000_0000_0000;
+        const TIDY =        0b1000_0000_0000_0000;
+        const FRAME =       0b0001_0000_0000_0000_0000;
+        const NOTE =        0b0010_0000_0000_0000_0000;
+        const ROUTING =     0b0100_0000_0000_0000_0000;
+        const SNAP =        0b1000_0000_0000_0000_0000;
+        const VALIDATE =    0b0001_0000_0000_0000_0000_0000;
+        const EVALUATE =    0b0010_0000_0000_0000_0000_0000;
     }
 }
 
+#[derive(Default)]
 pub struct InternalInput {
     // perf: Performance,
     dragging_lately: bool,
@@ -57,31 +73,12 @@ pub struct InternalInput {
     mouse_delta_till_mouse_down: Vec2,
 
     wheel_delta: f64,
+    double_click: bool,
 
     keys_lately: Keys,
     keys: Keys,
 }
 
-impl Default for InternalInput {
-    fn default() -> Self {
-        InternalInput {
-            // perf: web_sys::window().unwrap().performance().unwrap(),
-            dragging_lately: Default::default(),
-            dragging_now: Default::default(),
-            mouse_down_lately: Default::default(),
-            mouse_down: Default::default(),
-            // mouse_down_time: Default::default(),
-            mouse_down_pos: Default::default(),
-            mouse_delta_current: Default::default(),
-            mouse_delta_till_mouse_down: Default::default(),
-            keys_lately: Default::default(),
-            keys: Default::default(),
-            wheel_delta: Default::default(),
-            mouse_pos: Default::default(),
-        }
-    }
-}
-
 static mut _INPUT: Lazy<*mut InternalInput> =
     Lazy::new(|| Box::leak(Box::new(InternalInput::default())));
 pub struct Input;
@@ -124,9 +121,22 @@ impl InternalInput {
         match key_code {
             "KeyX" => Some(Keys::DELETE),
             "Space" => Some(Keys::MENU),
-            "Shift" => Some(Keys::SHIFT),
-            "Ctrl" => Some(Keys::CTRL),
-            "Alt" => Some(Keys::ALT),
+            "KeyS" => Some(Keys::SAVE),
+            "KeyZ" => Some(Keys::UNDO),
+            "KeyD" => Some(Keys::DUPLICATE),
+            "Delete" | "Backspace" => Some(Keys::REMOVE),
+            "KeyG" => Some(Keys::GROUP),
+            "Escape" => Some(Keys::BACK),
+            "KeyL" => Some(Keys::TIDY),
+            "KeyF" => Some(Keys::FRAME),
+            "KeyN" => Some(Keys::NOTE),
+            "KeyR" => Some(Keys::ROUTING),
+            "KeyP" => Some(Keys::SNAP),
+            "KeyV" => Some(Keys::VALIDATE),
+            "KeyE" => Some(Keys::EVALUATE),
+            "Shift" | "ShiftLeft" | "ShiftRight" => Some(Keys::SHIFT),
+            "Ctrl" | "ControlLeft" | "ControlRight" => Some(Keys::CTRL),
+            "Alt" | "AltLeft" | "AltRight" => Some(Keys::ALT),
             "ArrowDown" => Some(Keys::ARROW_DOWN),
             "ArrowLeft" => Some(Keys::ARROW_LEFT),
             "ArrowUp" => Some(Keys::ARROW_UP),
@@ -140,6 +150,7 @@ impl InternalInput {
         self.mouse_down_lately = self.mouse_down;
         self.dragging_lately = self.dragging_now;
         self.wheel_delta = 0.0;
+        self.double_click = false;
     }
 
     fn update_ui(&self) {
@@ -187,6 +198,19 @@ impl InternalInput {
         self.update_ui();
     }
 
+    pub fn mouse_pos(&self) -> Point {
+        self.mouse_pos
+    }
+
+    pub fn on_double_click(&mut self, event: MouseEvent) {
+        self.update();
+        self.double_click = true;
+        self.mouse_pos = (event.x() as f64, event.y() as f64).into();
+        self.update_ui();
+    }
+
+    /// Not called yet, wheel zooms the tree directly, see `start`.
+    #[allow(dead_code)]
     pub fn on_wheel(&mut self, wheel: WheelEvent) {
         self.update();
         self.wheel_delta = wheel.delta_y();
@@ -198,6 +222,10 @@ impl InternalInput {
             return InputMouseEvent::Wheel(self.wheel_delta);
         }
 
+        if self.double_click {
+            return InputMouseEvent::DoubleClick(self.mouse_pos);
+        }
+
         if self.dragging_lately && self.dragging_now {
             return InputMouseEvent::Drag(self.mouse_pos, self.mouse_delta_current);
         }
@@ -225,6 +253,13 @@ impl InternalInput {
         InputMouseEvent::None
     }
 
+    /// Forgets pressed keys. Modal dialogs swallow key up events, so keys
+    /// pressed to open them would stay pressed otherwise.
+    pub fn release_keys(&mut self) {
+        self.keys = Keys::empty();
+        self.keys_lately = Keys::empty();
+    }
+
     pub fn on_key_up(&mut self, event: KeyboardEvent) {
         self.update();
         if let Some(key_code) = self.key_code_from_str(&event.code()) {
@@ -2,7 +2,7 @@
 //! cubic bezier segment i.e. a bezier line segment with 4 points,
 //! the two center ones acting as control points.
 
-use super::point::Point;
+use super::{line::Line, point::Point, rect::Rect};
 
 /// Represents a cubic bezier segment drawn between two points. The first point
 /// in the bezier segment is implicitly the end point of the previous segment.
@@ -28,6 +28,104 @@ impl BezierSegment {
             p3: p3.into(),
         }
     }
+
+    /// Point of the segment at `t` in `[0, 1]`, `from` is the start point
+    /// of the segment.
+    pub fn point_at(&self, from: impl Into<Point>, t: f64) -> Point {
+        let from = from.into();
+        let s = 1.0 - t;
+        let x = cubic(from.x, self.p1.x, self.p2.x, self.p3.x, s, t);
+        let y = cubic(from.y, self.p1.y, self.p2.y, self.p3.y, s, t);
+        Point { x, y }
+    }
+
+    /// Smallest rect containing the curve itself, not its control points.
+    pub fn bound_rect(&self, from: impl Into<Point>) -> Rect {
+        let from = from.into();
+        extrema(from.x, self.p1.x, self.p2.x, self.p3.x)
+            .into_iter()
+            .chain(extrema(from.y, self.p1.y, self.p2.y, self.p3.y))
+            .map(|t| self.point_at(from, t))
+            .fold(Rect::from_points(from, self.p3), |rect, x| {
+                rect.combined_with(Rect::from_points(x, x))
+            })
+    }
+
+    /// Approximates the curve with lines which deviate from it by at most
+    /// `tolerance`.
+    pub fn flatten(&self, from: impl Into<Point>, tolerance: f64) -> Vec<Line> {
+        let from = from.into();
+        // Wang's formula: uniform subdivision into this many lines keeps the
+        // error under tolerance.
+        let second_difference = |a: Point, b: Point, c: Point| ((a - b) - (b - c)).len();
+        let deviation = second_difference(from, self.p1, self.p2)
+            .max(second_difference(self.p1, self.p2, self.p3));
+        let count = ((0.75 * deviation / tolerance).sqrt().ceil() as usize).max(1);
+
+        let points = (0..=count)
+            .map(|i| self.point_at(from, i as f64 / count as f64))
+            .collect::<Vec<_>>();
+        points
+            .windows(2)
+            .map(|x| Line {
+                start: x[0],
+                end: x[1],
+            })
+            .collect()
+    }
+
+    /// Distance from the point to the closest point of the curve, accurate up
+    /// to `tolerance`.
+    pub fn distance_to(
+        &self,
+        from: impl Into<Point>,
+        point: impl Into<Point>,
+        tolerance: f64,
+    ) -> f64 {
+        let point = point.into();
+        self.flatten(from, tolerance)
+            .iter()
+            .map(|x| x.distance_to(point))
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+/// One coordinate of the cubic bezier curve, `s` is `1 - t`.
+fn cubic(a: f64, b: f64, c: f64, d: f64, s: f64, t: f64) -> f64 {
+    a * s * s * s + 3.0 * b * s * s * t + 3.0 * c * s * t * t + d * t * t * t
+}
+
+/// Values of `t` inside of the segment where one coordinate of the curve has
+/// extremum, i.e. roots of the derivative.
+fn extrema(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // Derivative divided by 3 is `qa * t^2 + qb * t + qc`.
+    let (u, v, w) = (b - a, c - b, d - c);
+    let (qa, qb, qc) = (u - 2.0 * v + w, 2.0 * (v - u), u);
+
+    let roots = if qa.abs() < 1e-12 {
+        if qb.abs() < 1e-12 {
+            [None, None]
+        } else {
+            [Some(-qc / qb), None]
+        }
+    } else {
+        let discriminant = qb * qb - 4.0 * qa * qc;
+        if discriminant < 0.0 {
+            [None, None]
+        } else {
+            let root = discriminant.sqrt();
+            [
+                Some((-qb + root) / (2.0 * qa)),
+                Some((-qb - root) / (2.0 * qa)),
+            ]
+        }
+    };
+    roots
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|t| (0.0..=1.0).contains(t))
+        .collect()
 }
 
 impl<P1, P2, P3> From<(P1, P2, P3)> for BezierSegment
@@ -45,3 +143,36 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{super::line::AsLine, *};
+
+    #[test]
+    fn follows_the_curve() {
+        // Arch from (0, 0) to (100, 0) peaking at y = -75.
+        let from = Point::new(0.0, 0.0);
+        let segment = BezierSegment::new((0.0, -100.0), (100.0, -100.0), (100.0, 0.0));
+        assert_eq!(segment.point_at(from, 0.5), (50.0, -75.0).into());
+
+        let rect = segment.bound_rect(from);
+        assert_eq!(rect, Rect::new(0.0, -75.0, 100.0, 0.0));
+
+        assert!(segment.distance_to(from, (50.0, -75.0), 0.1) < 0.1);
+        // Middle of the chord is far from the curve.
+        assert!(segment.distance_to(from, (50.0, 0.0), 0.1) > 40.0);
+
+        let lines = segment.flatten(from, 0.1);
+        let crosses = |start: (f64, f64), end: (f64, f64)| {
+            lines.iter().any(|x| {
+                x.is_intersect(Line {
+                    start: start.into(),
+                    end: end.into(),
+                })
+            })
+        };
+        assert!(crosses((50.0, -80.0), (50.0, -70.0)));
+        // Crosses the chord only.
+        assert!(!crosses((50.0, -10.0), (50.0, 10.0)));
+    }
+}
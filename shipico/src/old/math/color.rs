@@ -125,10 +125,10 @@ impl Color {
 
         if s.starts_with('#') {
             s = s.trim_start_matches('#');
-            if !s.chars().all(|c| c.is_digit(16)) {
+            if !s.chars().all(|c| c.is_ascii_hexdigit()) {
                 return ColorParseResult::BadHexFormat;
             }
-        } else if !s.is_empty() || !s.chars().all(|c| c.is_digit(16)) {
+        } else if !s.is_empty() || !s.chars().all(|c| c.is_ascii_hexdigit()) {
             return ColorParseResult::ColorNotFound;
         }
         if s.len() > 8 {
@@ -192,19 +192,16 @@ pub enum ColorParseError {
 
 impl std::fmt::Display for ColorParseError {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(fmt, "{}", self)
-    }
-}
-
-impl std::error::Error for ColorParseError {
-    fn description(&self) -> &str {
-        match self {
+        let message = match self {
             ColorParseError::ColorNotFound => "Color not found",
             ColorParseError::BadHexFormat => "Bad hex format",
-        }
+        };
+        write!(fmt, "{}", message)
     }
 }
 
+impl std::error::Error for ColorParseError {}
+
 impl std::str::FromStr for Color {
     type Err = ColorParseError;
     fn from_str(s: &str) -> Result<Color, ColorParseError> {
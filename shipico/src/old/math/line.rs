@@ -6,6 +6,20 @@ pub struct Line {
     pub end: Point,
 }
 
+impl Line {
+    /// Distance from the point to the closest point of the segment.
+    pub fn distance_to(&self, point: impl Into<Point>) -> f64 {
+        let point = point.into();
+        let direction = self.end - self.start;
+        let length = direction.len_squared();
+        if length == 0.0 {
+            return (point - self.start).len();
+        }
+        let t = ((point - self.start).dot(direction) / length).clamp(0.0, 1.0);
+        (point - (self.start + direction * t)).len()
+    }
+}
+
 pub trait AsLine: Clone + Copy {
     fn start(&self) -> Point;
     fn end(&self) -> Point;
@@ -27,7 +41,9 @@ pub trait AsLine: Clone + Copy {
         }
         let lambda = ((y4 - y3) * (x4 - x1) + (x3 - x4) * (y4 - y1)) / det;
         let gamma = ((y1 - y2) * (x4 - x1) + (x2 - x1) * (y4 - y1)) / det;
-        return (0.0 < lambda && lambda < 1.0) && (0.0 < gamma && gamma < 1.0);
+        // Touching counts, so lines passing through the joint of a polyline
+        // still hit it.
+        (0.0..=1.0).contains(&lambda) && (0.0..=1.0).contains(&gamma)
     }
 }
 
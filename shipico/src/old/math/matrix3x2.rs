@@ -5,7 +5,6 @@
 use super::point::Point;
 use super::vec2::Vec2;
 
-use std::f64::EPSILON;
 use std::ops::Mul;
 
 /// The 2D affine identity matrix.
@@ -362,7 +361,7 @@ impl Matrix {
 
     #[inline]
     fn det_shows_invertible(det: f64) -> bool {
-        det.abs() > EPSILON
+        det.abs() > f64::EPSILON
     }
 }
 
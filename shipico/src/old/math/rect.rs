@@ -5,7 +5,6 @@ use super::size::Size;
 use super::thickness::Thickness;
 use super::vec2::Vec2;
 
-use std::f64::{INFINITY, NEG_INFINITY};
 use std::ops::{Add, Sub};
 
 /// Represents a rectangle defined by the coordinates of the upper-left corner
@@ -39,10 +38,10 @@ pub enum RectCorner {
 impl Rect {
     /// A rect that holds the entire real space
     pub const INFINITE: Rect = Rect {
-        left: NEG_INFINITY,
-        top: NEG_INFINITY,
-        right: INFINITY,
-        bottom: INFINITY,
+        left: f64::NEG_INFINITY,
+        top: f64::NEG_INFINITY,
+        right: f64::INFINITY,
+        bottom: f64::INFINITY,
     };
 
     /// Constructs the rectangle from components.
@@ -1,29 +1,27 @@
-#![feature(type_alias_impl_trait)]
-#![feature(associated_type_defaults)]
 #![allow(unused_unsafe)]
+#![cfg_attr(test, feature(test))]
 
 use input::input;
 use math::{AsLine, Point, Rect, Vec2};
 
 use canvas::Canvas;
-use tree::{NodeId, SocketId, SocketKind};
+use tree::{Edit, Routing, Severity, Snapping, SocketId, SocketKind};
 use ui::*;
 use wasm_bindgen::{prelude::*, JsCast};
 
 mod canvas;
 mod capabilities;
-mod function;
+pub mod function;
 mod input;
-mod math;
-mod params;
+pub mod math;
+pub mod params;
 mod temp_styles;
-mod tree;
+pub mod tree;
 mod ui;
 mod widget;
 
 use function::*;
-use params::*;
-use web_sys::Event;
+use web_sys::{ClipboardEvent, Event};
 pub use widget::{Shape, Widget, WidgetStyleExt};
 
 #[macro_export]
@@ -33,7 +31,10 @@ macro_rules! log {
         // so i wrapped it in unsafe block
         // Now it complains that unsafe is unnecessary.
         // Fuck you, RA
-        unsafe{ web_sys::console::log_1(&format_args!($($t)*).to_string().into()) }
+        {
+            let message = format_args!($($t)*).to_string();
+            unsafe{ web_sys::console::log_1(&message.into()) }
+        }
     }
 }
 
@@ -46,9 +47,14 @@ macro_rules! log {
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 pub struct Settings {
+    // Key codes are for the key handlers commented out in `start`.
+    #[allow(dead_code)]
     delete_key_code: String,
+    #[allow(dead_code)]
     menu_key_code: String,
     zoom_speed: f64,
+    routing: Routing,
+    snapping: Snapping,
 }
 
 impl Default for Settings {
@@ -57,6 +63,8 @@ impl Default for Settings {
             delete_key_code: "KeyX".to_string(),
             menu_key_code: "Space".to_string(),
             zoom_speed: 1.0,
+            routing: Routing::Curved,
+            snapping: Snapping::default(),
         }
     }
 }
@@ -79,11 +87,15 @@ pub fn start() -> Result<(), JsValue> {
     });
 
     // resize canvas width and height according to new window dimensions
-    set_on_resize(|x| {
+    set_on_resize(|_| {
         ui().canvas.reset_canvas_size();
     });
 
     set_on_keydown(|x| {
+        // Browser would save the page, search or go back otherwise.
+        if (x.ctrl_key() && (x.code() == "KeyS" || x.code() == "KeyG")) || x.code() == "Backspace" {
+            x.prevent_default();
+        }
         input().on_key_down(x);
         // match x.code() {
         //     key if key == ui().settings.delete_key_code => {
@@ -128,7 +140,7 @@ pub fn start() -> Result<(), JsValue> {
         };
         let pivot = (x.x() as f64, x.y() as f64);
 
-        ui().tree.zoom(delta, pivot.into());
+        ui().current_mut().zoom(delta, pivot.into());
         ui().redraw();
     });
 
@@ -195,6 +207,38 @@ pub fn start() -> Result<(), JsValue> {
         // }
     });
 
+    // Clipboard events are fired on the document, not on the window.
+    let document = ui().canvas.window.document().unwrap();
+    let on_copy = Closure::wrap(Box::new(|event: ClipboardEvent| {
+        let data = match (event.clipboard_data(), ui().copy_selection()) {
+            (Some(data), Some(text)) => data.set_data("text/plain", &text),
+            _ => return,
+        };
+        if data.is_ok() {
+            // Otherwise browser puts its own selection to the clipboard.
+            event.prevent_default();
+        }
+    }) as Box<dyn FnMut(ClipboardEvent)>);
+    document.set_oncopy(Some(on_copy.as_ref().unchecked_ref()));
+    on_copy.forget();
+
+    let on_paste = Closure::wrap(Box::new(|event: ClipboardEvent| {
+        let text = event
+            .clipboard_data()
+            .and_then(|data| data.get_data("text/plain").ok());
+        if let Some(text) = text {
+            event.prevent_default();
+            ui().paste(&text, input().mouse_pos());
+            ui().redraw();
+        }
+    }) as Box<dyn FnMut(ClipboardEvent)>);
+    document.set_onpaste(Some(on_paste.as_ref().unchecked_ref()));
+    on_paste.forget();
+
+    set_on_dblclick(|x| {
+        input().on_double_click(x);
+    });
+
     set_on_mousedown(|x| {
         input().on_mouse_down(x);
 
@@ -224,74 +268,67 @@ pub fn start() -> Result<(), JsValue> {
         // }
     });
 
-    ui().tree.create_node(
-        FunctionDefinition {
-            inputs: &[],
-            outputs: &[ParamType::f64],
-            // outputs: &[],
-            name: "input_geo",
-        },
-        (200.0, 200.0).into(),
-    );
-
-    ui().tree.create_node(
-        FunctionDefinition {
-            inputs: &[ParamType::f64, ParamType::f64],
-            outputs: &[ParamType::f64],
-            name: "boolean",
-        },
-        (300.0, 450.0).into(),
-    );
-
-    ui().tree.create_node(
-        FunctionDefinition {
-            inputs: &[ParamType::f64],
-            outputs: &[],
-            name: "output_geo",
-        },
-        (200.0, 700.0).into(),
-    );
+    if !ui().load() {
+        create_demo_tree();
+    }
+
+    ui().canvas.debug = false;
+
+    ui().redraw();
+    ui().redraw();
+
+    Ok(())
+}
+
+fn create_demo_tree() {
+    let function = |name: &str| FunctionDefinition::find(name).unwrap().clone();
+
+    let input = ui()
+        .tree
+        .create_node(function("input_geo"), (200.0, 200.0).into());
+
+    let boolean = ui()
+        .tree
+        .create_node(function("boolean"), (300.0, 450.0).into());
+
+    ui().tree
+        .create_node(function("output_geo"), (200.0, 700.0).into());
 
     for i in 0..10 {
         for k in 0..10 {
             ui().tree.create_node(
-                FunctionDefinition {
-                    inputs: &[ParamType::f64],
-                    outputs: &[],
-                    name: "output_geo",
-                },
+                function("output_geo"),
                 (i as f64 * 200.0, k as f64 * 100.0).into(),
             );
         }
     }
 
     ui().tree
-        .create_connection((0, 0, SocketKind::Output), (1, 1, SocketKind::Input));
+        .create_connection(
+            (input, 0, SocketKind::Output),
+            (boolean, 1, SocketKind::Input),
+        )
+        .unwrap();
     ui().tree
-        .create_connection((0, 0, SocketKind::Output), (1, 0, SocketKind::Input));
-
-    ui().canvas.debug = false;
-
-    ui().redraw();
-    ui().redraw();
-
-    Ok(())
+        .create_connection(
+            (input, 0, SocketKind::Output),
+            (boolean, 0, SocketKind::Input),
+        )
+        .unwrap();
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 enum Action {
     DragScreen,
-    DragNode(NodeId),
+    DragSelection,
     DragSocket(SocketId),
+    SelectRect,
+    #[default]
     None,
 }
 
-impl Default for Action {
-    fn default() -> Self {
-        Action::None
-    }
-}
-
+/// State of the old input handlers commented out in `start`.
+#[allow(dead_code)]
 #[derive(Default)]
 pub struct InputState {
     mouse_down: bool,
@@ -305,6 +342,9 @@ pub struct InputState {
     cut_line: Option<CutLine>,
 }
 
+/// Menu of the functions, opened by the key handlers commented out in
+/// `start`.
+#[allow(dead_code)]
 #[derive(Default)]
 pub struct FloatingWindow {
     position: Point,
@@ -315,18 +355,171 @@ pub struct FloatingWindow {
 struct PhantomConnection {
     from: Point,
     to: Point,
+    /// Kind of the socket connection is dragged from.
+    kind: SocketKind,
 }
 
 impl Widget for PhantomConnection {
     fn draw(&self, canvas: &mut Canvas) {
+        let (output, input) = match self.kind {
+            SocketKind::Output => (self.from, self.to),
+            SocketKind::Input => (self.to, self.from),
+        };
+        let curve = tree::connection_curve(output, input);
         temp_styles::connection(canvas);
         canvas.begin_path();
-        canvas.move_to(self.from);
-        canvas.line_to(self.to);
+        canvas.move_to(output);
+        canvas.bezier_curve_to(curve.p1, curve.p2, curve.p3);
         canvas.stroke();
     }
 }
 
+/// Rubber band user drags to select several nodes.
+#[derive(Clone, Copy, Debug)]
+struct SelectionRect {
+    from: Point,
+    to: Point,
+}
+
+impl SelectionRect {
+    fn rect(&self) -> Rect {
+        Rect::from_points(self.from, self.to)
+    }
+}
+
+impl Widget for SelectionRect {
+    fn draw(&self, canvas: &mut Canvas) {
+        canvas.set_shadow_blur(0.0);
+        canvas.set_line_width(1.0);
+        canvas.set_stroke_style("#F2A65A");
+        canvas.set_fill_style("#F2A65A20");
+        canvas.fill_rect(self.rect());
+        canvas.stroke_rect(self.rect());
+    }
+}
+
+/// Lines in screen space showing which nodes the dragged ones align with.
+#[derive(Clone, Debug)]
+struct AlignmentGuides {
+    lines: Vec<(Point, Point)>,
+}
+
+impl Widget for AlignmentGuides {
+    fn draw(&self, canvas: &mut Canvas) {
+        canvas.set_shadow_blur(0.0);
+        canvas.set_line_width(1.0);
+        canvas.set_stroke_style("#F2A65A");
+        canvas.begin_path();
+        for &(start, end) in &self.lines {
+            canvas.move_to(start);
+            canvas.line_to(end);
+        }
+        canvas.stroke();
+    }
+}
+
+/// Path from the root tree to the group user is in.
+///
+/// Clicking a crumb goes back to that level.
+struct Breadcrumbs {
+    names: Vec<String>,
+}
+
+impl Breadcrumbs {
+    const LEFT: f64 = 400.0;
+    const TOP: f64 = 20.0;
+    const CRUMB_W: f64 = 160.0;
+    const CRUMB_H: f64 = 40.0;
+
+    fn crumb_rect(i: usize) -> Rect {
+        let left = Self::LEFT + Self::CRUMB_W * i as f64;
+        Rect::new(
+            left,
+            Self::TOP,
+            left + Self::CRUMB_W,
+            Self::TOP + Self::CRUMB_H,
+        )
+    }
+
+    /// Depth of the crumb under the mouse.
+    fn crumb_at(&self, pos: Point) -> Option<usize> {
+        (0..self.names.len()).find(|&i| Self::crumb_rect(i).contains_point(pos))
+    }
+}
+
+impl Widget for Breadcrumbs {
+    fn draw(&self, canvas: &mut Canvas) {
+        canvas.set_shadow_blur(0.0);
+        canvas.set_line_width(1.0);
+        canvas.set_font("20px sans-serif");
+        for (i, name) in self.names.iter().enumerate() {
+            let rect = Self::crumb_rect(i);
+            canvas.set_fill_style("#25232388");
+            canvas.set_stroke_style("#F5F1ED");
+            canvas.fill_rect(rect);
+            canvas.stroke_rect(rect);
+            canvas.set_fill_style("#F5F1ED");
+            canvas
+                .render_context
+                .fill_text(name, rect.left + 10.0, rect.bottom - 12.0)
+                .unwrap();
+        }
+    }
+}
+
+/// List of the problems [`tree::Tree::validate`] found.
+///
+/// Clicking a row selects the node with the problem.
+struct DiagnosticsPanel {
+    rows: Vec<(Severity, String)>,
+}
+
+impl DiagnosticsPanel {
+    const LEFT: f64 = 50.0;
+    const TOP: f64 = 250.0;
+    const ROW_W: f64 = 500.0;
+    const ROW_H: f64 = 30.0;
+
+    fn row_rect(i: usize) -> Rect {
+        let top = Self::TOP + Self::ROW_H * i as f64;
+        Rect::new(Self::LEFT, top, Self::LEFT + Self::ROW_W, top + Self::ROW_H)
+    }
+
+    /// Index of the row under the mouse.
+    fn row_at(&self, pos: Point) -> Option<usize> {
+        (0..self.rows.len()).find(|&i| Self::row_rect(i).contains_point(pos))
+    }
+}
+
+impl Widget for DiagnosticsPanel {
+    fn draw(&self, canvas: &mut Canvas) {
+        canvas.set_shadow_blur(0.0);
+        canvas.set_font("16px sans-serif");
+        canvas.set_fill_style("#F5F1ED");
+        if self.rows.is_empty() {
+            canvas
+                .render_context
+                .fill_text("no problems found", Self::LEFT, Self::TOP + 20.0)
+                .unwrap();
+        }
+        for (i, (severity, text)) in self.rows.iter().enumerate() {
+            let rect = Self::row_rect(i);
+            canvas.set_fill_style("#25232388");
+            canvas.fill_rect(rect);
+            canvas.set_fill_style(match severity {
+                Severity::Error => "#E4572E",
+                Severity::Warning => "#F3C969",
+            });
+            canvas.fill_rect(Rect::new(rect.left, rect.top, rect.left + 6.0, rect.bottom));
+            canvas.set_fill_style("#F5F1ED");
+            canvas
+                .render_context
+                .fill_text(text, rect.left + 16.0, rect.bottom - 9.0)
+                .unwrap();
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 struct CutLine {
     start: Point,
@@ -373,6 +566,7 @@ impl Widget for CutLine {
     }
 }
 
+#[allow(dead_code)]
 impl FloatingWindow {
     const FUNCTION_H: f64 = 50.0;
     const FUNCTION_W: f64 = 200.0;
@@ -383,14 +577,14 @@ impl FloatingWindow {
             self.position.x,
             self.position.y,
             Self::FUNCTION_W,
-            Self::FUNCTION_H * function::FUNCTIONS.len() as f64,
+            Self::FUNCTION_H * Self::functions().len() as f64,
         );
         context.set_fill_style("#9999");
         context.render_context.fill_rect(
             self.position.x,
             self.position.y,
             Self::FUNCTION_W,
-            Self::FUNCTION_H * function::FUNCTIONS.len() as f64,
+            Self::FUNCTION_H * Self::functions().len() as f64,
         );
         let font_size = Self::FUNCTION_W / 10.0;
         context.set_fill_style("#111");
@@ -417,20 +611,45 @@ impl FloatingWindow {
                 .unwrap();
         };
 
-        for i in 0..function::FUNCTIONS.len() {
-            fill_text(i, function::FUNCTIONS[i].name);
+        for (i, function) in Self::functions().iter().enumerate() {
+            fill_text(i, function.title);
         }
+
+        // Category of each function, small in the corner of its row.
+        context.set_fill_style("#444");
+        context
+            .render_context
+            .set_font(&format!("{}px sans-serif", font_size / 2.0));
+        for (i, function) in Self::functions().iter().enumerate() {
+            context
+                .render_context
+                .fill_text(
+                    function.category,
+                    self.position.x + (Self::FUNCTION_W * 0.1),
+                    self.position.y + (Self::FUNCTION_H * i as f64) + font_size / 2.0,
+                )
+                .unwrap();
+        }
+    }
+
+    /// Registered functions, grouped by category.
+    fn functions() -> Vec<&'static FunctionDefinition> {
+        let registry = FunctionRegistry::global();
+        let mut functions = registry.functions().to_vec();
+        let categories = registry.categories();
+        functions.sort_by_key(|x| categories.iter().position(|&c| c == x.category));
+        functions
     }
 
     fn bound_rect(&self) -> Rect {
         Rect::from_center_size(
             (
                 self.position.x + Self::FUNCTION_W / 2.0,
-                self.position.y + (Self::FUNCTION_H * function::FUNCTIONS.len() as f64) / 2.0,
+                self.position.y + (Self::FUNCTION_H * Self::functions().len() as f64) / 2.0,
             ),
             (
                 Self::FUNCTION_W,
-                (Self::FUNCTION_H * function::FUNCTIONS.len() as f64),
+                (Self::FUNCTION_H * Self::functions().len() as f64),
             ),
         )
     }
@@ -442,7 +661,7 @@ impl FloatingWindow {
 
         if bounding_rect.contains_point(pos) {
             let y = pos.y;
-            for i in 0..function::FUNCTIONS.len() {
+            for i in 0..Self::functions().len() {
                 if i as f64 * Self::FUNCTION_H + self.position.y < y
                     && i as f64 * Self::FUNCTION_H + Self::FUNCTION_H + self.position.y > y
                 {
@@ -455,8 +674,10 @@ impl FloatingWindow {
 
     fn on_click(&self) {
         if let Some(selected) = self.selected {
-            ui().tree
-                .create_node(function::FUNCTIONS[selected].clone(), self.position);
+            ui().execute(Edit::CreateNode {
+                function: Self::functions()[selected].clone(),
+                position: self.position,
+            });
         }
     }
 }
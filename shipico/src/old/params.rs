@@ -4,11 +4,12 @@ macro_rules! params {
         use ::std::any::TypeId;
 
         #[allow(non_camel_case_types)]
-        #[derive(Clone, Debug, PartialEq)]
+        #[derive(Clone, Debug, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
         pub enum Param {
             $($t($t)),+,
         }
 
+        #[allow(non_camel_case_types)]
         #[derive(Debug, Clone, Copy, PartialEq, Eq)]
         pub enum ParamType {
             $($t),+,
@@ -46,6 +47,27 @@ macro_rules! params {
             pub const fn is_unknown(&self) -> bool {
                 matches!(self, ParamType::Unknown)
             }
+
+            /// Inverse of the `Display` implementation.
+            pub fn find(name: &str) -> Option<ParamType> {
+                match name {
+                    $(
+                        stringify!($t) => Some(ParamType::$t),
+                    )+
+                    "Unknown" => Some(ParamType::Unknown),
+                    _ => None,
+                }
+            }
+        }
+
+        impl ::std::fmt::Display for Param {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                match self {
+                    $(
+                        Param::$t(value) => write!(f, "{}", value)
+                    ),+
+                }
+            }
         }
 
         $(
@@ -57,6 +79,18 @@ macro_rules! params {
         )+
 
         impl Param {
+            /// Parses the value of the `ty` type written the way `Display`
+            /// writes it, see [`Param::is_finite`] for the values rejected.
+            pub fn parse(ty: ParamType, text: &str) -> Option<Param> {
+                match ty {
+                    $(
+                        ParamType::$t => text.parse::<$t>().ok().map(Param::$t),
+                    )+
+                    ParamType::Unknown => None,
+                }
+                .filter(Param::is_finite)
+            }
+
             pub fn get_type(&self) -> ParamType{
                 match self {
                     $(
@@ -87,7 +121,19 @@ macro_rules! params {
     }
 }
 
-params!(i64, f64, f32);
+params!(i64, f64, f32, bool);
+
+impl Param {
+    /// Whether the value is not NaN or infinite. JSON has no such numbers,
+    /// so documents can't hold them.
+    pub fn is_finite(&self) -> bool {
+        match self {
+            Param::f64(x) => x.is_finite(),
+            Param::f32(x) => x.is_finite(),
+            Param::i64(_) | Param::bool(_) => true,
+        }
+    }
+}
 
 #[test]
 fn check() {
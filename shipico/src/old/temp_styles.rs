@@ -1,7 +1,6 @@
-use wasm_bindgen::JsValue;
-
-use crate::{canvas::Canvas, math::Color};
+use crate::canvas::Canvas;
 
+#[allow(dead_code)]
 pub fn node(context: &Canvas) {
     // shadow color
     context.set_shadow_color("#1B264F");
@@ -31,6 +30,7 @@ pub fn delete_line(context: &Canvas) {
     context.set_line_cap("round");
 }
 
+#[allow(dead_code)]
 pub fn dot(context: &Canvas) {
     // input dot color
     context.set_fill_style("#DAD2BC");
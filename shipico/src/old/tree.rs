@@ -1,14 +1,52 @@
-use std::hash::Hash;
+use std::{collections::HashSet, hash::Hash};
 
 use crate::{
     function::FunctionDefinition,
     log,
-    math::{AsLine, Ellipse, Line, Matrix, Point, Rect, RoundedRect, Size, Vec2},
-    widget::{Component, Stack, Widget},
-    Shape, WidgetStyleExt,
+    math::{AsLine, BezierSegment, Ellipse, Line, Matrix, Point, Rect, RoundedRect, Size, Vec2},
+    params::{Param, ParamType},
+    widget::{Component, Stack, Text, Widget},
+    WidgetStyleExt,
 };
 
-pub type NodeId = usize;
+mod annotation;
+mod document;
+mod evaluation;
+mod group;
+mod history;
+mod layout;
+mod program;
+mod reroute;
+mod routing;
+mod selection;
+mod snapping;
+mod spatial;
+mod validation;
+mod values;
+
+pub use annotation::Frame;
+pub use document::DocumentError;
+pub use evaluation::{Counters, Evaluation, Executor};
+pub use group::Group;
+pub use history::{Edit, History};
+pub use program::{Instruction, Program, ProgramError};
+pub use routing::Routing;
+pub use snapping::{Snapping, GRID_STEPS};
+pub use spatial::QuadTree;
+pub use validation::{Diagnostic, Problem, Severity};
+
+use routing::Wire;
+use spatial::SpatialItem;
+
+/// Handle to the node in the [`Tree`].
+///
+/// Handles stay valid when other nodes are removed, and handle of the removed
+/// node is never given to another node, so stale handles can always be detected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct SocketId {
@@ -125,7 +163,7 @@ impl AsSocketId for OutputSocketId {
     }
 }
 
-impl AsSocketId for (usize, usize, SocketKind) {
+impl AsSocketId for (NodeId, usize, SocketKind) {
     fn node(&self) -> NodeId {
         self.0
     }
@@ -142,13 +180,105 @@ impl AsSocketId for (usize, usize, SocketKind) {
 const NODE_POINT_RADIUS: f64 = 4.0;
 const NODE_POINT_COLLISION_RADIUS: f64 = NODE_POINT_RADIUS * 1.5;
 const NODE_CONNECTION_WIDTH: f64 = 4.0;
+/// How far from the connection curve a click still hits it.
+const CONNECTION_HIT_DISTANCE: f64 = NODE_CONNECTION_WIDTH * 1.5;
+/// Max error of the lines connection curves are approximated with in hit tests.
+const CONNECTION_TOLERANCE: f64 = 0.25;
+
+/// Curve of the connection from the output socket at `from` to the input socket at `to`.
+///
+/// Outputs are on the right side of the node and inputs are on the left, so the
+/// curve leaves to the right and arrives from the left.
+pub fn connection_curve(from: Point, to: Point) -> BezierSegment {
+    let bend = ((to.x - from.x).abs() / 2.0).max(50.0);
+    BezierSegment::new(from + [bend, 0.0], to - [bend, 0.0], to)
+}
 
-struct NodeData {
-    function: FunctionDefinition,
+#[derive(Clone)]
+pub struct NodeData {
+    kind: NodeKind,
 
     sockets: Vec<Socket>,
+    /// Values of the unconnected inputs set on this node, by input index,
+    /// see [`NodeData::input_value`].
+    values: Vec<Option<Param>>,
 
     position: Point,
+    /// Size of the body, see [`NodeData::content_size`].
+    size: Size,
+}
+
+/// What node computes.
+#[derive(Clone)]
+pub enum NodeKind {
+    Function(FunctionDefinition),
+    Group(Box<Group>),
+    /// Reroute knot, passes the value of its type through unchanged.
+    Reroute(ParamType),
+    /// Comment frame, see [`Frame`].
+    Frame(Box<Frame>),
+    /// Sticky note with the text.
+    Note(String),
+}
+
+impl NodeKind {
+    pub fn name(&self) -> &str {
+        match self {
+            NodeKind::Function(function) => function.title,
+            NodeKind::Group(group) => &group.name,
+            NodeKind::Reroute(_) => "reroute",
+            NodeKind::Frame(frame) => &frame.title,
+            NodeKind::Note(_) => "note",
+        }
+    }
+
+    pub fn inputs(&self) -> &[ParamType] {
+        match self {
+            NodeKind::Function(function) => function.inputs,
+            NodeKind::Group(group) => &group.inputs,
+            NodeKind::Reroute(ty) => std::slice::from_ref(ty),
+            NodeKind::Frame(_) | NodeKind::Note(_) => &[],
+        }
+    }
+
+    pub fn outputs(&self) -> &[ParamType] {
+        match self {
+            NodeKind::Function(function) => function.outputs,
+            NodeKind::Group(group) => &group.outputs,
+            NodeKind::Reroute(ty) => std::slice::from_ref(ty),
+            NodeKind::Frame(_) | NodeKind::Note(_) => &[],
+        }
+    }
+
+    /// Name of the input, shown next to its socket.
+    pub fn input_name(&self, index: usize) -> String {
+        match self {
+            NodeKind::Function(function) => function.input_names[index].to_string(),
+            NodeKind::Group(group) => group.input_name(index),
+            _ => String::new(),
+        }
+    }
+
+    /// Name of the output, shown next to its socket.
+    pub fn output_name(&self, index: usize) -> String {
+        match self {
+            NodeKind::Function(function) => function.output_names[index].to_string(),
+            NodeKind::Group(group) => group.output_name(index),
+            _ => String::new(),
+        }
+    }
+}
+
+impl From<FunctionDefinition> for NodeKind {
+    fn from(function: FunctionDefinition) -> Self {
+        NodeKind::Function(function)
+    }
+}
+
+impl From<Group> for NodeKind {
+    fn from(group: Group) -> Self {
+        NodeKind::Group(Box::new(group))
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -160,33 +290,97 @@ pub enum SocketKind {
 #[derive(Clone, Copy, Debug)]
 struct Socket {
     enabled: bool,
+    /// Input has a default value, so it can be left unconnected.
+    optional: bool,
     position: Point,
     kind: SocketKind,
+    ty: ParamType,
 }
 
 #[derive(Debug, Clone)]
 struct Connection {
-    line: Line,
+    /// Follows the sockets, see [`Tree::update_wires_around`].
+    wire: Wire,
     input: InputSocketId,
     output: OutputSocketId,
 }
 
+#[derive(Clone)]
+struct NodeSlot {
+    generation: u32,
+    node: Option<NodeData>,
+}
+
+#[derive(Clone, Default)]
 pub struct Tree {
     connections: Vec<Connection>,
-    nodes: Vec<NodeData>,
+    nodes: Vec<NodeSlot>,
+    /// Indices of the empty slots in `nodes`.
+    free_slots: Vec<u32>,
+    /// Every created node gets unique generation, so the same `NodeId`
+    /// is never used twice.
+    next_generation: u32,
     transform: Matrix,
+    /// Socket user is dragging connection from.
+    pending_connection: Option<SocketId>,
+    selection: Vec<NodeId>,
+    /// Nodes with problems found by [`Tree::validate`], outlined in the
+    /// color of the severity.
+    highlights: Vec<(NodeId, Severity)>,
+    /// Bound rects of the nodes and connections in canvas space.
+    index: QuadTree<SpatialItem>,
+    routing: Routing,
+}
+
+/// Everything that was removed from the tree along with the node.
+pub struct RemovedNode {
+    pub id: NodeId,
+    pub node: NodeData,
+    pub connections: Vec<(InputSocketId, OutputSocketId)>,
 }
 
-impl Default for Tree {
-    fn default() -> Self {
-        Tree {
-            connections: Default::default(),
-            nodes: Default::default(),
-            transform: Default::default(),
+/// Resolved connection between two sockets, see [`Tree::can_connect`].
+#[derive(Debug)]
+pub struct Link {
+    pub input: InputSocketId,
+    pub output: OutputSocketId,
+    /// Function to convert output value to the input type, if types differ.
+    pub conversion: Option<&'static FunctionDefinition>,
+}
+
+/// The reason why two sockets can't be connected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionError {
+    /// Socket belongs to the node that does not exist anymore.
+    InvalidSocket,
+    SameNode,
+    SameKind,
+    TypeMismatch {
+        output: ParamType,
+        input: ParamType,
+    },
+    /// Input node is upstream of the output node already.
+    Cycle,
+}
+
+impl std::fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConnectionError::InvalidSocket => write!(f, "socket does not exist"),
+            ConnectionError::SameNode => write!(f, "can't connect node to itself"),
+            ConnectionError::SameKind => {
+                write!(f, "only inputs can be connected to outputs")
+            }
+            ConnectionError::TypeMismatch { output, input } => {
+                write!(f, "can't connect `{}` output to `{}` input", output, input)
+            }
+            ConnectionError::Cycle => write!(f, "connection would create a cycle"),
         }
     }
 }
 
+impl std::error::Error for ConnectionError {}
+
 #[derive(Debug)]
 pub enum CastResult {
     Node(NodeId),
@@ -243,25 +437,167 @@ impl Tree {
 
         let new_transform = self.transform * Matrix::scaling(1.0 + delta, pivot);
         let new_zoom = new_transform.a;
-        if new_zoom >= ZOOM_MIN && new_zoom <= ZOOM_MAX {
+        if (ZOOM_MIN..=ZOOM_MAX).contains(&new_zoom) {
             self.transform = new_transform;
         }
     }
 
-    pub fn create_node(&mut self, function: FunctionDefinition, position: Point) {
+    /// Converts vector from screen space to canvas space.
+    pub fn canvas_vector(&self, screen_vector: Vec2) -> Vec2 {
+        screen_vector / self.z()
+    }
+
+    pub fn create_node(&mut self, function: FunctionDefinition, position: Point) -> NodeId {
         let mut node = NodeData::new(function);
         node.position = self.screen_to_canvas(position);
-        self.nodes.push(node);
+        self.insert_node(node)
+    }
+
+    fn insert_node(&mut self, node: NodeData) -> NodeId {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        let rect = node.bound_rect();
+
+        let index = match self.free_slots.pop() {
+            Some(index) => {
+                self.nodes[index as usize] = NodeSlot {
+                    generation,
+                    node: Some(node),
+                };
+                index
+            }
+            None => {
+                self.nodes.push(NodeSlot {
+                    generation,
+                    node: Some(node),
+                });
+                self.nodes.len() as u32 - 1
+            }
+        };
+
+        let id = NodeId { index, generation };
+        self.index.insert(SpatialItem::Node(id), rect);
+        self.update_wires_around(id, &[rect]);
+        id
+    }
+
+    /// Puts removed node back under its old id.
+    ///
+    /// Returns `false` if the slot of the node is already taken by another node.
+    /// It never happens as long as all the edits go through the [`History`].
+    fn restore_node(&mut self, id: NodeId, node: NodeData) -> bool {
+        let slot = match self.nodes.get_mut(id.index as usize) {
+            Some(slot) if slot.node.is_none() => slot,
+            _ => return false,
+        };
+
+        let rect = node.bound_rect();
+        slot.generation = id.generation;
+        slot.node = Some(node);
+        self.free_slots.retain(|&x| x != id.index);
+        self.index.insert(SpatialItem::Node(id), rect);
+        self.update_wires_around(id, &[rect]);
+        true
+    }
+
+    /// Removes node along with all of its connections.
+    ///
+    /// Returns `None` if the node does not exist anymore.
+    pub fn remove_node(&mut self, id: NodeId) -> Option<RemovedNode> {
+        self.node(id)?;
+
+        let inputs = self
+            .connections
+            .iter()
+            .filter(|x| x.input.node() == id || x.output.node() == id)
+            .map(|x| x.input)
+            .collect::<Vec<_>>();
+        let connections = inputs
+            .into_iter()
+            .filter_map(|input| self.remove_connection(input))
+            .map(|x| (x.input, x.output))
+            .collect();
+
+        let node = self.nodes[id.index as usize].node.take()?;
+        self.free_slots.push(id.index);
+        self.index.remove(SpatialItem::Node(id));
+        self.update_wires_around(id, &[node.bound_rect()]);
+        self.deselect(id);
+
+        Some(RemovedNode {
+            id,
+            node,
+            connections,
+        })
+    }
+
+    /// Node with `id`, or `None` if the node has been removed.
+    pub fn node(&self, id: NodeId) -> Option<&NodeData> {
+        self.nodes
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut NodeData> {
+        self.nodes
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+    }
+
+    /// Iterates over all nodes in the order they are drawn.
+    pub fn nodes(&self) -> impl DoubleEndedIterator<Item = (NodeId, &NodeData)> {
+        self.nodes.iter().enumerate().filter_map(|(index, slot)| {
+            slot.node.as_ref().map(|node| {
+                (
+                    NodeId {
+                        index: index as u32,
+                        generation: slot.generation,
+                    },
+                    node,
+                )
+            })
+        })
     }
 
     fn set_socket_state(&mut self, socket: impl AsSocketId, new_state: bool) {
-        let node = &mut self.nodes[socket.node()];
-        let mut socket = &mut node.sockets[socket.id()];
-        socket.enabled = new_state;
+        if let Some(socket) = self
+            .node_mut(socket.node())
+            .and_then(|node| node.sockets.get_mut(socket.id()))
+        {
+            socket.enabled = new_state;
+        }
     }
 
     fn socket_position(&self, socket: impl AsSocketId) -> Point {
-        self.nodes[socket.node()].socket_position(socket.id())
+        self.node(socket.node())
+            .map(|node| node.socket_position(socket.id()))
+            .unwrap_or_default()
+    }
+
+    fn socket_type(&self, socket: impl AsSocketId) -> ParamType {
+        self.node(socket.node())
+            .map(|node| node.sockets[socket.id()].ty)
+            .unwrap_or(ParamType::Unknown)
+    }
+
+    /// Name of the input or output the socket stands for.
+    fn socket_name(&self, socket: impl AsSocketId) -> Option<String> {
+        let node = self.node(socket.node())?;
+        let index = node.socket_index(socket.id());
+        Some(match socket.kind() {
+            SocketKind::Input => node.kind.input_name(index),
+            SocketKind::Output => node.kind.output_name(index),
+        })
+    }
+
+    /// Checks that socket belongs to an existing node and has requested kind.
+    fn is_valid_socket(&self, socket: impl AsSocketId) -> bool {
+        self.node(socket.node())
+            .and_then(|node| node.sockets.get(socket.id()))
+            .map(|x| x.kind == socket.kind())
+            .unwrap_or(false)
     }
 
     pub fn delete_connection(&mut self, input_id: InputSocketId) {
@@ -271,6 +607,7 @@ impl Tree {
     fn remove_connection(&mut self, input_id: InputSocketId) -> Option<Connection> {
         if let Some(connection_pos) = self.connections.iter().position(|x| x.input == input_id) {
             let connection = self.connections.swap_remove(connection_pos);
+            self.index.remove(SpatialItem::Connection(input_id));
 
             self.set_socket_state(connection.input, false);
             if !self
@@ -286,9 +623,28 @@ impl Tree {
         }
     }
 
-    pub fn create_connection(&mut self, from: impl AsSocketId, to: impl AsSocketId) {
-        if from.is_same_node(to) || from.is_same_kind(to) {
-            return;
+    /// Output socket connected to the `input`, if any.
+    pub fn connected_output(&self, input: InputSocketId) -> Option<OutputSocketId> {
+        self.connections
+            .iter()
+            .find(|x| x.input == input)
+            .map(|x| x.output)
+    }
+
+    /// Checks whether two sockets can be connected and how.
+    pub fn can_connect(
+        &self,
+        from: impl AsSocketId,
+        to: impl AsSocketId,
+    ) -> Result<Link, ConnectionError> {
+        if !self.is_valid_socket(from) || !self.is_valid_socket(to) {
+            return Err(ConnectionError::InvalidSocket);
+        }
+        if from.is_same_node(to) {
+            return Err(ConnectionError::SameNode);
+        }
+        if from.is_same_kind(to) {
+            return Err(ConnectionError::SameKind);
         }
 
         let (input, output) = match from.kind() {
@@ -296,14 +652,106 @@ impl Tree {
             SocketKind::Output => (to.into_input(), from.into_output()),
         };
 
-        // if there are already connection with this input id and this output id just return.
-        if self
-            .connections
+        if self.is_downstream(input.node(), output.node()) {
+            return Err(ConnectionError::Cycle);
+        }
+
+        let input_type = self.socket_type(input);
+        let output_type = self.socket_type(output);
+        let conversion = if input_type == output_type {
+            None
+        } else {
+            Some(
+                FunctionDefinition::conversion(output_type, input_type).ok_or(
+                    ConnectionError::TypeMismatch {
+                        output: output_type,
+                        input: input_type,
+                    },
+                )?,
+            )
+        };
+
+        Ok(Link {
+            input,
+            output,
+            conversion,
+        })
+    }
+
+    /// Checks whether `target` node is reachable from `node` by following
+    /// connections from outputs to inputs.
+    fn is_downstream(&self, node: NodeId, target: NodeId) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            if node == target {
+                return true;
+            }
+            if visited.insert(node) {
+                stack.extend(
+                    self.connections
+                        .iter()
+                        .filter(|x| x.output.node() == node)
+                        .map(|x| x.input.node()),
+                );
+            }
+        }
+        false
+    }
+
+    /// Highlights sockets that can be connected to `from` while user drags connection.
+    pub fn set_pending_connection(&mut self, from: Option<SocketId>) {
+        self.pending_connection = from;
+    }
+
+    /// Outlines nodes with problems, replacing the previous highlights.
+    pub fn set_highlights(&mut self, highlights: Vec<(NodeId, Severity)>) {
+        self.highlights = highlights;
+    }
+
+    /// The most severe problem highlighted on the node.
+    fn highlight(&self, node: NodeId) -> Option<Severity> {
+        self.highlights
             .iter()
-            .find(|x| x.input == input)
-            .map(|connection| connection.output == output)
-            .unwrap_or(false)
-        {
+            .filter(|(x, _)| *x == node)
+            .map(|&(_, severity)| severity)
+            .max()
+    }
+
+    /// Connects two sockets.
+    ///
+    /// If socket types differ, but one can be safely converted to another,
+    /// conversion node is inserted in between and its id is returned.
+    pub fn create_connection(
+        &mut self,
+        from: impl AsSocketId,
+        to: impl AsSocketId,
+    ) -> Result<Option<NodeId>, ConnectionError> {
+        let link = self.can_connect(from, to)?;
+
+        let conversion = match link.conversion {
+            Some(function) => function,
+            None => {
+                self.connect_sockets(link.input, link.output);
+                return Ok(None);
+            }
+        };
+
+        let start = self.socket_position(link.output);
+        let end = self.socket_position(link.input);
+        let mut node = NodeData::new(conversion.clone());
+        node.position = start + (end - start) / 2.0;
+        let node = self.insert_node(node);
+
+        self.connect_sockets((node, 0, SocketKind::Input).into_input(), link.output);
+        self.connect_sockets(link.input, (node, 1, SocketKind::Output).into_output());
+        Ok(Some(node))
+    }
+
+    /// Connects sockets without any checks.
+    fn connect_sockets(&mut self, input: InputSocketId, output: OutputSocketId) {
+        // if there are already connection with this input id and this output id just return.
+        if self.connected_output(input) == Some(output) {
             // connection already exists
             return;
         }
@@ -315,14 +763,14 @@ impl Tree {
 
         self.set_socket_state(input, true);
         self.set_socket_state(output, true);
-        self.connections.push(Connection {
-            line: Line {
-                start: self.socket_position(input),
-                end: self.socket_position(output),
-            },
+        let connection = Connection {
+            wire: self.wire(output, input),
             input,
             output,
-        });
+        };
+        self.index
+            .insert(SpatialItem::Connection(input), connection.bound_rect());
+        self.connections.push(connection);
     }
 
     pub fn line_cast(&self, line: impl AsLine) -> Vec<CastResult> {
@@ -330,17 +778,43 @@ impl Tree {
             start: self.screen_to_canvas(line.start()),
             end: self.screen_to_canvas(line.end()),
         };
-        self.connections
-            .iter()
-            .filter(|x| x.line.is_intersect(line))
+        self.index
+            .query_segment(line)
+            .into_iter()
+            .filter_map(|item| match item {
+                SpatialItem::Connection(input) => self.connection(input),
+                SpatialItem::Node(_) => None,
+            })
+            .filter(|x| x.intersects(line))
             .map(|x| CastResult::Connection(x.input))
             .collect()
     }
 
+    fn connection(&self, input: InputSocketId) -> Option<&Connection> {
+        self.connections.iter().find(|x| x.input == input)
+    }
+
     pub fn point_cast(&self, point: Point) -> CastResult {
         let point = self.screen_to_canvas(point);
-        for (node_id, node) in self.nodes.iter().enumerate().rev() {
-            if node.bound_rect().contains_point(point) {
+        let mut nodes = Vec::new();
+        let mut connections = Vec::new();
+        // Sockets stick out of the node edges by their radius.
+        let area = Rect::from_points(point, point).expanded_by(NODE_POINT_COLLISION_RADIUS);
+        for item in self.index.query_rect(area) {
+            match item {
+                SpatialItem::Node(id) => nodes.extend(self.node(id).map(|node| (id, node))),
+                SpatialItem::Connection(input) => connections.extend(self.connection(input)),
+            }
+        }
+        // Nodes drawn last are on top, frames are behind everything.
+        nodes.sort_unstable_by_key(|(id, node)| (node.is_frame(), std::cmp::Reverse(id.index)));
+
+        for (node_id, node) in nodes {
+            let rect = node.hit_rect();
+            if rect
+                .expanded_by(NODE_POINT_COLLISION_RADIUS)
+                .contains_point(point)
+            {
                 for socket in 0..node.sockets.len() {
                     let world_position = node.socket_position(socket);
                     if NODE_POINT_COLLISION_RADIUS >= (point - world_position).len() {
@@ -354,17 +828,14 @@ impl Tree {
                         );
                     }
                 }
-
+            }
+            if rect.contains_point(point) {
                 return CastResult::Node(node_id);
             }
         }
 
-        for connection in self.connections.iter() {
-            if connection.line.bound_rect().contains_point(point)
-                && connection
-                    .line
-                    .are_collinear(point, NODE_CONNECTION_WIDTH * 1.5)
-            {
+        for connection in connections {
+            if connection.is_hit(point) {
                 return CastResult::Connection(connection.input);
             }
         }
@@ -372,72 +843,168 @@ impl Tree {
         CastResult::None
     }
 
-    pub fn drag_node(&mut self, node: NodeId, delta: Vec2) {
-        self.nodes[node].position += self.transform.transform_vector(delta);
+    pub fn drag_node(&mut self, node: NodeId, delta: Vec2) -> bool {
+        self.move_node(node, self.canvas_vector(delta))
+    }
+
+    /// Moves node by offset in canvas space, connections follow it.
+    ///
+    /// Returns `false` if the node does not exist anymore.
+    pub fn move_node(&mut self, node: NodeId, offset: Vec2) -> bool {
+        match self.node_mut(node) {
+            Some(data) => {
+                let old = data.bound_rect();
+                data.position += offset;
+                let rect = data.bound_rect();
+                self.index.insert(SpatialItem::Node(node), rect);
+                self.update_wires_around(node, &[old, rect]);
+                true
+            }
+            None => false,
+        }
     }
 }
 
 impl NodeData {
-    const SIZE: Size = Size {
-        height: 100.0,
-        width: 200.0,
-    };
+    /// Nodes are at least this wide, wider if their labels need it.
+    const MIN_WIDTH: f64 = 160.0;
     const CORNER_RADIUS: f64 = 10.0;
 
-    const HOR_PADDING: f64 = Self::SIZE.width / 10.0;
-    const VER_PADDING: f64 = Self::SIZE.height / 6.0;
-
-    const LEFT_SIDE: f64 = -Self::SIZE.width / 2.0;
-    const RIGHT_SIDE: f64 = Self::SIZE.width / 2.0;
-    const TOP_SIDE: f64 = -Self::SIZE.height / 2.0;
-    const BOTTOM_SIDE: f64 = Self::SIZE.height / 2.0;
-
-    const LEFT_DOT_X: f64 = Self::LEFT_SIDE + Self::HOR_PADDING;
-    const RIGHT_DOT_X: f64 = Self::RIGHT_SIDE - Self::HOR_PADDING;
-
-    const OUTPUT_DOT_Y: f64 = Self::BOTTOM_SIDE - Self::VER_PADDING;
-    const INPUT_DOT_Y: f64 = Self::TOP_SIDE + Self::VER_PADDING;
+    const TITLE_HEIGHT: f64 = 30.0;
+    const TITLE_FONT_SIZE: f64 = 16.0;
+    /// Every input and output gets a row with its label, inputs go first.
+    const ROW_HEIGHT: f64 = 24.0;
+    const LABEL_FONT_SIZE: f64 = 13.0;
+    /// Space between the labels and the sides of the node.
+    const LABEL_MARGIN: f64 = 12.0;
+    const BOTTOM_PADDING: f64 = 8.0;
+    /// Average character width relative to the font size. Text can't be
+    /// measured without a canvas, so label widths are estimated.
+    const CHAR_WIDTH: f64 = 0.6;
+
+    /// Reroute knots are just big enough to grab them between their sockets.
+    const KNOT_RADIUS: f64 = 12.0;
+
+    pub fn new(kind: impl Into<NodeKind>) -> NodeData {
+        let mut node = NodeData {
+            kind: kind.into(),
+            sockets: Vec::new(),
+            values: Vec::new(),
+            position: Default::default(),
+            size: Default::default(),
+        };
+        node.layout();
+        node
+    }
 
-    pub fn new(function: FunctionDefinition) -> NodeData {
-        let input_count = function.inputs.len();
-        let output_count = function.outputs.len();
+    /// Places the sockets and sizes the body to fit the labels, connected
+    /// sockets stay enabled.
+    fn layout(&mut self) {
+        let socket = |kind, optional, ty, position| Socket {
+            enabled: false,
+            optional,
+            ty,
+            position,
+            kind,
+        };
+        let sockets = if let NodeKind::Reroute(ty) = self.kind {
+            self.size = Size::from(Self::KNOT_RADIUS * 2.0);
+            vec![
+                socket(
+                    SocketKind::Input,
+                    false,
+                    ty,
+                    (-Self::KNOT_RADIUS, 0.0).into(),
+                ),
+                socket(
+                    SocketKind::Output,
+                    false,
+                    ty,
+                    (Self::KNOT_RADIUS, 0.0).into(),
+                ),
+            ]
+        } else {
+            let size = self.content_size();
+            let row_y = |row: usize| {
+                -size.height / 2.0 + Self::TITLE_HEIGHT + Self::ROW_HEIGHT * (row as f64 + 0.5)
+            };
+            let input_count = self.kind.inputs().len();
+            self.size = size;
+
+            self.kind
+                .inputs()
+                .iter()
+                .enumerate()
+                .map(|(i, &ty)| {
+                    let optional = self.kind.input_default(i).is_some();
+                    let position = (-size.width / 2.0, row_y(i)).into();
+                    socket(SocketKind::Input, optional, ty, position)
+                })
+                .chain(self.kind.outputs().iter().enumerate().map(|(i, &ty)| {
+                    let position = (size.width / 2.0, row_y(input_count + i)).into();
+                    socket(SocketKind::Output, false, ty, position)
+                }))
+                .collect::<Vec<_>>()
+        };
 
-        let input_spacing = (Self::RIGHT_DOT_X - Self::LEFT_DOT_X) / (input_count + 1) as f64;
-        let output_spacing = (Self::RIGHT_DOT_X - Self::LEFT_DOT_X) / (output_count + 1) as f64;
+        let enabled = self.sockets.iter().map(|x| x.enabled);
+        self.sockets = sockets
+            .into_iter()
+            .zip(enabled.chain(std::iter::repeat(false)))
+            .map(|(socket, enabled)| Socket { enabled, ..socket })
+            .collect();
+    }
 
-        let sockets = function
-            .inputs
+    /// Labels of the inputs and outputs in the order of the sockets. Inputs
+    /// that have a value without a connection show it.
+    fn labels(&self) -> impl Iterator<Item = String> + '_ {
+        let kind = &self.kind;
+        let inputs =
+            kind.inputs()
+                .iter()
+                .enumerate()
+                .map(move |(i, ty)| match self.input_value(i) {
+                    Some(value) => format!("{}: {} = {}", kind.input_name(i), ty, value),
+                    None => format!("{}: {}", kind.input_name(i), ty),
+                });
+        let outputs = kind
+            .outputs()
             .iter()
             .enumerate()
-            .map(|(i, _)| Socket {
-                enabled: false,
-                position: (
-                    input_spacing * (i as f64 + 1.0) + Self::LEFT_DOT_X,
-                    Self::INPUT_DOT_Y,
-                )
-                    .into(),
-                kind: SocketKind::Input,
-            })
-            .chain(function.outputs.iter().enumerate().map(|(i, _)| {
-                Socket {
-                    enabled: false,
-                    position: (
-                        output_spacing * (i as f64 + 1.0) + Self::LEFT_DOT_X,
-                        Self::OUTPUT_DOT_Y,
-                    )
-                        .into(),
-                    kind: SocketKind::Output,
-                }
-            }))
-            .collect::<Vec<_>>();
+            .map(move |(i, ty)| format!("{}: {}", kind.output_name(i), ty));
+        inputs.chain(outputs)
+    }
 
-        NodeData {
-            sockets,
-            function,
-            position: Default::default(),
+    /// Size fitting the title and a row for every input and output.
+    fn content_size(&self) -> Size {
+        let text_width = |text: &str, font_size: f64| {
+            text.chars().count() as f64 * font_size * Self::CHAR_WIDTH + Self::LABEL_MARGIN * 2.0
+        };
+        let width = self
+            .labels()
+            .map(|x| text_width(&x, Self::LABEL_FONT_SIZE))
+            .fold(
+                text_width(self.kind.name(), Self::TITLE_FONT_SIZE),
+                f64::max,
+            )
+            .max(Self::MIN_WIDTH);
+        let rows = self.kind.inputs().len() + self.kind.outputs().len();
+
+        Size {
+            width,
+            height: Self::TITLE_HEIGHT + rows as f64 * Self::ROW_HEIGHT + Self::BOTTOM_PADDING,
         }
     }
 
+    pub fn kind(&self) -> &NodeKind {
+        &self.kind
+    }
+
+    /// Position of the node center in canvas space.
+    pub fn position(&self) -> Point {
+        self.position
+    }
+
     #[inline]
     fn socket_position(&self, socket_id: usize) -> Point {
         self.sockets[socket_id].position + self.position.to_vector()
@@ -448,25 +1015,59 @@ impl NodeData {
         self.sockets[socket_id].kind
     }
 
+    /// Index of the socket among the sockets of the same kind.
+    fn socket_index(&self, socket_id: usize) -> usize {
+        match self.get_socket_kind(socket_id) {
+            SocketKind::Input => socket_id,
+            SocketKind::Output => socket_id - self.kind.inputs().len(),
+        }
+    }
+
+    /// Inverse of [`NodeData::socket_index`].
+    fn socket_id(&self, kind: SocketKind, index: usize) -> Option<usize> {
+        match kind {
+            SocketKind::Input if index < self.kind.inputs().len() => Some(index),
+            SocketKind::Output if index < self.kind.outputs().len() => {
+                Some(self.kind.inputs().len() + index)
+            }
+            _ => None,
+        }
+    }
+
     fn bound_rect(&self) -> Rect {
-        Rect::from_center_size(self.position, Self::SIZE)
+        let size = self.annotation_size().unwrap_or(self.size);
+        Rect::from_center_size(self.position, size)
     }
 }
 
-impl Line {
-    fn are_collinear(&self, point: Point, tolerance: f64) -> bool {
-        let a = self.start;
-        let b = self.end;
-        let c = point;
-        let slope_delta = (a.y - b.y) * (a.x - c.x) - (a.y - c.y) * (a.x - b.x);
-        tolerance > (slope_delta.abs()) / 100.0
+impl Connection {
+    /// Rect in which clicks can hit the connection.
+    fn bound_rect(&self) -> Rect {
+        self.wire.bound_rect().expanded_by(CONNECTION_HIT_DISTANCE)
+    }
+
+    fn is_hit(&self, point: Point) -> bool {
+        self.wire.distance_to(point) <= CONNECTION_HIT_DISTANCE
+    }
+
+    fn intersects(&self, line: Line) -> bool {
+        self.wire.intersects(line)
     }
 }
 
-impl Component for Socket {
-    fn build(&self) -> Box<dyn Widget> {
+impl Socket {
+    /// Builds socket, dimmed if it can't be used in the current action.
+    fn build_with(&self, available: bool) -> Box<dyn Widget> {
         const RADIUS: f64 = 4.0;
-        if !self.enabled {
+        // Optional inputs are blue, so it's clear they can be left alone.
+        let color = match (self.optional, available) {
+            (false, true) => "#DAD2BC",
+            (false, false) => "#DAD2BC40",
+            (true, true) => "#8FB8DE",
+            (true, false) => "#8FB8DE40",
+        };
+
+        let socket = if !self.enabled {
             Ellipse::round(self.position, RADIUS).stroked().boxed()
         } else {
             Stack::from(vec![
@@ -474,8 +1075,14 @@ impl Component for Socket {
                 Ellipse::round(self.position, RADIUS * 0.4).filled().boxed(),
             ])
             .boxed()
+        };
+
+        if available && !self.optional {
+            socket
+        } else {
+            socket.with_stroke_style(color).boxed()
         }
-        .with_fill_style("#DAD2BC")
+        .with_fill_style(color)
         .with_shadow_color("#1B264F")
         .with_shadow_blur(4.0)
         .with_shadow_offset(0.0, 0.0)
@@ -485,31 +1092,72 @@ impl Component for Socket {
     }
 }
 
-impl Component for NodeData {
+impl Component for Socket {
     fn build(&self) -> Box<dyn Widget> {
-        const RREC: RoundedRect = RoundedRect {
-            rect: Rect {
-                left: NodeData::LEFT_SIDE,
-                top: NodeData::TOP_SIDE,
-                right: NodeData::RIGHT_SIDE,
-                bottom: NodeData::BOTTOM_SIDE,
-            },
-            radius_x: NodeData::CORNER_RADIUS,
-            radius_y: NodeData::CORNER_RADIUS,
+        self.build_with(true)
+    }
+}
+
+impl NodeData {
+    /// Builds node, dimming sockets for which `available` returns `false`.
+    fn build_with(
+        &self,
+        selected: bool,
+        highlight: Option<Severity>,
+        available: impl Fn(usize) -> bool,
+    ) -> Box<dyn Widget> {
+        if let Some(annotation) = self.build_annotation(selected) {
+            return annotation;
+        }
+
+        let rect = Rect::from_center_size(Point::ORIGIN, self.size);
+        let fill = match self.kind {
+            NodeKind::Function(_) => "#25232388",
+            NodeKind::Group(_) => "#3B4F6B88",
+            NodeKind::Reroute(_) => "#A99985",
+            NodeKind::Frame(_) | NodeKind::Note(_) => {
+                unreachable!("annotations are built separately")
+            }
         };
 
-        let node_rect = RREC
-            .with_shadow_blur(10.0)
-            .with_fill_style("#25232388")
-            .with_stroke_style("#F5F1ED")
-            .with_line_width(2.5)
-            .with_shadow_offset(0.0, 5.0)
+        let body = match self.kind {
+            NodeKind::Reroute(_) => Ellipse::round(Point::ORIGIN, NodeData::KNOT_RADIUS * 0.6)
+                .stroked()
+                .filled()
+                .boxed(),
+            _ => RoundedRect {
+                rect,
+                radius_x: NodeData::CORNER_RADIUS,
+                radius_y: NodeData::CORNER_RADIUS,
+            }
             .stroked()
             .filled()
+            .boxed(),
+        };
+        let node_rect = body
+            .with_shadow_blur(10.0)
+            .with_fill_style(fill)
+            .with_stroke_style(match (selected, highlight) {
+                (true, _) => "#F2A65A",
+                (false, Some(Severity::Error)) => "#E4572E",
+                (false, Some(Severity::Warning)) => "#F3C969",
+                (false, None) => "#F5F1ED",
+            })
+            .with_line_width(if selected || highlight.is_some() {
+                4.0
+            } else {
+                2.5
+            })
+            .with_shadow_offset(0.0, 5.0)
             .inspect(|| log!("drawing node body -----------------"))
             .boxed();
 
-        let iter = std::iter::once(node_rect).chain(self.sockets.iter().map(|x| x.build()));
+        let iter = std::iter::once(node_rect).chain(self.build_labels()).chain(
+            self.sockets
+                .iter()
+                .enumerate()
+                .map(|(i, x)| x.build_with(available(i))),
+        );
 
         Stack::of(iter)
             .translated(self.position.to_vector())
@@ -518,30 +1166,261 @@ impl Component for NodeData {
     }
 }
 
+impl NodeData {
+    /// Builds the title and the labels next to the sockets, nothing for
+    /// reroute knots.
+    fn build_labels(&self) -> Option<Box<dyn Widget>> {
+        if let NodeKind::Reroute(_) = self.kind {
+            return None;
+        }
+
+        let rect = Rect::from_center_size(Point::ORIGIN, self.size);
+        let title = Text {
+            text: self.kind.name().to_string(),
+            position: (
+                rect.left + Self::LABEL_MARGIN,
+                rect.top + (Self::TITLE_HEIGHT - Self::TITLE_FONT_SIZE) / 2.0,
+            )
+                .into(),
+            size: Self::TITLE_FONT_SIZE,
+        }
+        .with_text_align("left")
+        .boxed();
+
+        let labels = self.labels().zip(&self.sockets).map(|(text, socket)| {
+            let (x, align) = match socket.kind {
+                SocketKind::Input => (rect.left + Self::LABEL_MARGIN, "left"),
+                SocketKind::Output => (rect.right - Self::LABEL_MARGIN, "right"),
+            };
+            Text {
+                text,
+                position: (x, socket.position.y - Self::LABEL_FONT_SIZE / 2.0).into(),
+                size: Self::LABEL_FONT_SIZE,
+            }
+            .with_text_align(align)
+            .boxed()
+        });
+
+        Some(
+            Stack::of(std::iter::once(title).chain(labels))
+                .with_fill_style("#F5F1ED")
+                .with_shadow_blur(0.0)
+                .inspect(|| log!("drawing node labels -----------------"))
+                .boxed(),
+        )
+    }
+}
+
+impl Component for NodeData {
+    fn build(&self) -> Box<dyn Widget> {
+        self.build_with(false, None, |_| true)
+    }
+}
+
 impl Component for Connection {
     fn build(&self) -> Box<dyn Widget> {
-        self.line
-            .with_shadow_blur(3.0)
-            .with_stroke_style("#A99985")
-            .with_line_width(4.0)
-            .stroked()
-            .inspect(|| log!("drawing connection -----------------"))
-            .boxed()
+        match &self.wire {
+            Wire::Curve { start, curve } => (*start, *curve).boxed(),
+            Wire::Polyline(points) => points.clone().boxed(),
+        }
+        .with_shadow_blur(3.0)
+        .with_stroke_style("#A99985")
+        .with_line_width(4.0)
+        .stroked()
+        .inspect(|| log!("drawing connection -----------------"))
+        .boxed()
     }
 }
 
 impl Component for Tree {
     fn build(&self) -> Box<dyn Widget> {
         Stack::from(vec![
+            Stack::of(
+                self.nodes()
+                    .filter(|(_, node)| node.is_frame())
+                    .map(|(id, node)| node.build_with(self.is_selected(id), None, |_| true)),
+            )
+            .inspect(|| log!("start drawing frames ---------------"))
+            .boxed(),
             Stack::of(self.connections.iter().map(|x| x.build()))
                 .inspect(|| log!("start drawing connections ---------------"))
                 .boxed(),
-            Stack::of(self.nodes.iter().map(|x| x.build()))
-                .inspect(|| log!("start drawing all nodes ---------------"))
-                .boxed(),
+            Stack::of(
+                self.nodes()
+                    .filter(|(_, node)| !node.is_frame())
+                    .map(|(id, node)| {
+                        let highlight = self.highlight(id);
+                        node.build_with(self.is_selected(id), highlight, |socket| {
+                            match self.pending_connection {
+                                Some(from) => {
+                                    let socket = (id, socket, node.get_socket_kind(socket));
+                                    from == socket.into_generic()
+                                        || self.can_connect(from, socket).is_ok()
+                                }
+                                None => true,
+                            }
+                        })
+                    }),
+            )
+            .inspect(|| log!("start drawing all nodes ---------------"))
+            .boxed(),
         ])
         .transformed(self.transform)
         .inspect(|| log!("drawing tree -----------------"))
         .boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Function `name` of the standard library, the nodes of the test trees
+    /// are made of.
+    pub(crate) fn function(name: &str) -> FunctionDefinition {
+        FunctionDefinition::find(name).unwrap().clone()
+    }
+
+    pub(crate) fn socket(node: NodeId, index: usize, kind: SocketKind) -> SocketId {
+        (node, index, kind).into_generic()
+    }
+
+    #[test]
+    fn stale_node_ids() {
+        let mut tree = Tree::new();
+        let function = FunctionDefinition::find("boolean").unwrap();
+        let removed = tree.create_node(function.clone(), (0.0, 0.0).into());
+        tree.remove_node(removed).unwrap();
+        let reused = tree.create_node(function.clone(), (0.0, 0.0).into());
+
+        // The slot is reused, but the old id must not point to the new node.
+        assert_eq!(reused.index, removed.index);
+        assert!(tree.node(removed).is_none());
+        assert!(tree.node(reused).is_some());
+        assert!(tree.remove_node(removed).is_none());
+        assert!(!tree.move_node(removed, [1.0, 1.0].into()));
+        assert_eq!(
+            tree.create_connection(
+                (removed, 2, SocketKind::Output),
+                (reused, 0, SocketKind::Input)
+            ),
+            Err(ConnectionError::InvalidSocket)
+        );
+    }
+
+    #[test]
+    fn cycles() {
+        let mut tree = Tree::new();
+        let boolean = FunctionDefinition::find("boolean").unwrap();
+        let nodes = (0..3)
+            .map(|_| tree.create_node(boolean.clone(), (0.0, 0.0).into()))
+            .collect::<Vec<_>>();
+        let output = |i: usize| (nodes[i], 2, SocketKind::Output);
+        let input = |i: usize| (nodes[i], 0, SocketKind::Input);
+
+        tree.create_connection(output(0), input(1)).unwrap();
+        tree.create_connection(output(1), input(2)).unwrap();
+
+        assert_eq!(
+            tree.can_connect(output(2), input(0)).err(),
+            Some(ConnectionError::Cycle)
+        );
+        assert_eq!(
+            tree.can_connect(input(1), output(2)).err(),
+            Some(ConnectionError::Cycle)
+        );
+        assert_eq!(
+            tree.create_connection(output(2), input(0)),
+            Err(ConnectionError::Cycle)
+        );
+        assert!(tree.can_connect(output(0), input(2)).is_ok());
+    }
+
+    #[test]
+    fn casts_follow_edits() {
+        let mut tree = Tree::new();
+        let function = FunctionDefinition::find("output_geo").unwrap();
+        let below = tree.create_node(function.clone(), (0.0, 0.0).into());
+        let above = tree.create_node(function.clone(), (50.0, 0.0).into());
+        assert!(matches!(tree.point_cast((30.0, 30.0).into()), CastResult::Node(x) if x == above));
+
+        tree.move_node(above, [1000.0, 0.0].into());
+        assert!(matches!(tree.point_cast((30.0, 30.0).into()), CastResult::Node(x) if x == below));
+        assert!(
+            matches!(tree.point_cast((1030.0, 30.0).into()), CastResult::Node(x) if x == above)
+        );
+
+        tree.remove_node(below);
+        assert!(matches!(
+            tree.point_cast((30.0, 30.0).into()),
+            CastResult::None
+        ));
+    }
+
+    #[test]
+    fn connections_are_curves() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (400.0, 400.0).into());
+        let target = (output, 0, SocketKind::Input).into_input();
+        tree.create_connection((input, 0, SocketKind::Output), target)
+            .unwrap();
+        let (start, curve) = match tree.connection(target).unwrap().wire {
+            Wire::Curve { start, curve } => (start, curve),
+            Wire::Polyline(_) => panic!("curves are the default"),
+        };
+        let chord = Line {
+            start,
+            end: curve.p3,
+        };
+        let on_curve = curve.point_at(start, 0.25);
+        let on_chord = chord.start + (chord.end - chord.start) * 0.25;
+
+        assert!(matches!(tree.point_cast(on_curve), CastResult::Connection(x) if x == target));
+        assert!(matches!(tree.point_cast(on_chord), CastResult::None));
+
+        let cut = |start: Point, end: Point| tree.line_cast((start, end)).len();
+        assert_eq!(cut(on_curve - [0.0, 20.0], on_curve + [0.0, 20.0]), 1);
+        assert_eq!(cut(on_chord - [0.0, 20.0], on_chord + [0.0, 20.0]), 0);
+    }
+
+    #[test]
+    fn nodes_fit_their_sockets() {
+        let mut tree = Tree::new();
+        let asd = tree.create_node(function("asd"), (0.0, 0.0).into());
+        let input = tree.create_node(function("input_geo"), (0.0, 500.0).into());
+        let node = |id| tree.node(id).unwrap();
+
+        // One row for every input and output.
+        let rows = node(asd).size.height - node(input).size.height;
+        assert_eq!(rows, NodeData::ROW_HEIGHT * 2.0);
+        let labels = node(asd).labels().collect::<Vec<_>>();
+        assert_eq!(labels, ["param1: i64", "param2: f32", "result: f32"]);
+
+        // Inputs are on the left side, outputs are on the right.
+        let rect = node(asd).bound_rect();
+        let (first, second, result) = (
+            tree.socket_position((asd, 0, SocketKind::Input)),
+            tree.socket_position((asd, 1, SocketKind::Input)),
+            tree.socket_position((asd, 2, SocketKind::Output)),
+        );
+        assert_eq!((first.x, result.x), (rect.left, rect.right));
+        assert_eq!(second.y - first.y, NodeData::ROW_HEIGHT);
+        assert_eq!(result.y - second.y, NodeData::ROW_HEIGHT);
+        assert!(matches!(
+            tree.point_cast(second),
+            CastResult::Socket(x, _) if x == (asd, 1, SocketKind::Input).into_generic()
+        ));
+
+        // Outer half of the socket sticks out of the node and still hits it.
+        let outside = NODE_POINT_RADIUS;
+        assert!(matches!(
+            tree.point_cast(first - [outside, 0.0]),
+            CastResult::Socket(x, _) if x == (asd, 0, SocketKind::Input).into_generic()
+        ));
+        assert!(matches!(
+            tree.point_cast(result + [outside, 0.0]),
+            CastResult::Socket(x, _) if x == (asd, 2, SocketKind::Output).into_generic()
+        ));
+    }
+}
@@ -0,0 +1,328 @@
+//! Comment frames and sticky notes.
+//!
+//! Annotations are nodes without sockets, so they are selected, moved, removed,
+//! copied and saved like any other node. Frames are drawn behind everything
+//! else and drag the nodes inside of them along.
+
+use crate::{
+    canvas::Canvas,
+    log,
+    math::{Point, Rect, RoundedRect, Size},
+    widget::{Stack, Text, Widget},
+    WidgetStyleExt,
+};
+
+use super::{NodeData, NodeId, NodeKind, SpatialItem, Tree};
+
+/// Backdrop with a title, groups nodes visually.
+#[derive(Clone)]
+pub struct Frame {
+    pub title: String,
+    /// Any CSS color.
+    pub color: String,
+    pub(super) size: Size,
+}
+
+impl Frame {
+    /// Frames are grabbed by the title bar, so the nodes and the canvas inside
+    /// of them can still be dragged.
+    pub const TITLE_HEIGHT: f64 = 40.0;
+    /// Colors new frames cycle through.
+    pub const COLORS: &'static [&'static str] = &["#4F6D7A", "#7A4F6D", "#6D7A4F", "#7A6D4F"];
+    /// Space between the framed nodes and the frame border.
+    const PADDING: f64 = 30.0;
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl NodeData {
+    const NOTE_WIDTH: f64 = 200.0;
+    const NOTE_LINE_HEIGHT: f64 = 20.0;
+    const NOTE_PADDING: f64 = 10.0;
+
+    /// Size of the annotation, `None` for the other nodes.
+    pub(super) fn annotation_size(&self) -> Option<Size> {
+        match &self.kind {
+            NodeKind::Frame(frame) => Some(frame.size),
+            NodeKind::Note(text) => {
+                let lines = text.lines().count().max(1) as f64;
+                Some(Size {
+                    width: Self::NOTE_WIDTH,
+                    height: lines * Self::NOTE_LINE_HEIGHT + Self::NOTE_PADDING * 2.0,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub(super) fn is_frame(&self) -> bool {
+        matches!(self.kind, NodeKind::Frame(_))
+    }
+
+    pub fn is_annotation(&self) -> bool {
+        matches!(self.kind, NodeKind::Frame(_) | NodeKind::Note(_))
+    }
+
+    /// Part of the node clicks hit.
+    pub(super) fn hit_rect(&self) -> Rect {
+        let rect = self.bound_rect();
+        match self.kind {
+            NodeKind::Frame(_) => Rect {
+                bottom: rect.top + Frame::TITLE_HEIGHT,
+                ..rect
+            },
+            _ => rect,
+        }
+    }
+
+    /// Builds frame or note, `None` for the other nodes.
+    pub(super) fn build_annotation(&self, selected: bool) -> Option<Box<dyn Widget>> {
+        let size = self.annotation_size()?;
+        let rect = Rect::from_center_size(Point::ORIGIN, size);
+        let border = if selected { "#F2A65A" } else { "#F5F1ED" };
+        let (body, text) = match &self.kind {
+            NodeKind::Frame(frame) => (
+                Backdrop {
+                    rect,
+                    color: frame.color.clone(),
+                    border,
+                }
+                .boxed(),
+                Text {
+                    text: frame.title.clone(),
+                    position: (rect.left + Self::NOTE_PADDING, rect.top).into(),
+                    size: Frame::TITLE_HEIGHT * 0.6,
+                },
+            ),
+            NodeKind::Note(note) => (
+                RoundedRect {
+                    rect,
+                    radius_x: 4.0,
+                    radius_y: 4.0,
+                }
+                .with_fill_style("#E8D9A0")
+                .with_stroke_style(border)
+                .with_line_width(if selected { 3.0 } else { 1.0 })
+                .stroked()
+                .filled()
+                .boxed(),
+                Text {
+                    text: note.clone(),
+                    position: (
+                        rect.left + Self::NOTE_PADDING,
+                        rect.top + Self::NOTE_PADDING,
+                    )
+                        .into(),
+                    size: Self::NOTE_LINE_HEIGHT * 0.8,
+                },
+            ),
+            _ => return None,
+        };
+        let text_color = match self.kind {
+            NodeKind::Note(_) => "#252323",
+            _ => "#F5F1ED",
+        };
+
+        Some(
+            Stack::from(vec![
+                body,
+                text.with_fill_style(text_color)
+                    .with_text_align("left")
+                    .boxed(),
+            ])
+            .with_shadow_blur(0.0)
+            .translated(self.position.to_vector())
+            .inspect(|| log!("drawing annotation -----------------"))
+            .boxed(),
+        )
+    }
+}
+
+/// Body of the frame, translucent so the grid behind stays visible.
+struct Backdrop {
+    rect: Rect,
+    color: String,
+    border: &'static str,
+}
+
+impl Widget for Backdrop {
+    fn draw(&self, canvas: &mut Canvas) {
+        let title = Rect {
+            bottom: self.rect.top + Frame::TITLE_HEIGHT,
+            ..self.rect
+        };
+        let outline = |canvas: &Canvas, rect: Rect| {
+            canvas.begin_path();
+            canvas.move_to((rect.left, rect.top));
+            canvas.line_to((rect.right, rect.top));
+            canvas.line_to((rect.right, rect.bottom));
+            canvas.line_to((rect.left, rect.bottom));
+            canvas.close_path();
+        };
+
+        canvas.set_fill_style(&self.color);
+        canvas.render_context.set_global_alpha(0.3);
+        outline(canvas, self.rect);
+        canvas.fill();
+        canvas.render_context.set_global_alpha(0.8);
+        outline(canvas, title);
+        canvas.fill();
+        canvas.render_context.set_global_alpha(1.0);
+
+        canvas.set_stroke_style(self.border);
+        canvas.set_line_width(2.0);
+        outline(canvas, self.rect);
+        canvas.stroke();
+    }
+}
+
+impl Tree {
+    /// Creates a frame covering the rect in screen space.
+    pub fn create_frame(&mut self, title: String, rect: Rect) -> NodeId {
+        let rect = Rect::from_points(
+            self.screen_to_canvas((rect.left, rect.top)),
+            self.screen_to_canvas((rect.right, rect.bottom)),
+        );
+        let color =
+            Frame::COLORS[self.nodes().filter(|(_, x)| x.is_frame()).count() % Frame::COLORS.len()];
+        let mut node = NodeData::new(NodeKind::Frame(Box::new(Frame {
+            title,
+            color: color.to_string(),
+            size: rect.size(),
+        })));
+        node.position = rect.center();
+        self.insert_node(node)
+    }
+
+    /// Creates a note with the top left corner at the screen position.
+    pub fn create_note(&mut self, text: String, position: Point) -> NodeId {
+        let mut node = NodeData::new(NodeKind::Note(text));
+        let size = node.annotation_size().unwrap();
+        node.position = self.screen_to_canvas(position) + [size.width / 2.0, size.height / 2.0];
+        self.insert_node(node)
+    }
+
+    /// Replaces frame title or note text and returns the old one.
+    ///
+    /// Returns `None` if the node is not an annotation.
+    pub fn set_text(&mut self, node: NodeId, text: String) -> Option<String> {
+        let data = self.node_mut(node)?;
+        let old = match &mut data.kind {
+            NodeKind::Frame(frame) => std::mem::replace(&mut frame.title, text),
+            NodeKind::Note(note) => std::mem::replace(note, text),
+            _ => return None,
+        };
+        // Note size depends on the text.
+        let rect = data.bound_rect();
+        self.index.insert(SpatialItem::Node(node), rect);
+        Some(old)
+    }
+
+    /// Text of the frame title or the note.
+    pub fn text(&self, node: NodeId) -> Option<&str> {
+        match &self.node(node)?.kind {
+            NodeKind::Frame(frame) => Some(&frame.title),
+            NodeKind::Note(note) => Some(note),
+            _ => None,
+        }
+    }
+
+    /// Rect in screen space fitting the nodes along with the frame title,
+    /// `None` if there are no such nodes.
+    pub fn frame_rect(&self, nodes: &[NodeId]) -> Option<Rect> {
+        let rect = nodes
+            .iter()
+            .filter_map(|&x| self.node(x))
+            .map(|x| x.bound_rect())
+            .reduce(|a, b| a.combined_with(b))?;
+        let rect = Rect {
+            left: rect.left - Frame::PADDING,
+            top: rect.top - Frame::PADDING - Frame::TITLE_HEIGHT,
+            right: rect.right + Frame::PADDING,
+            bottom: rect.bottom + Frame::PADDING,
+        };
+        Some(Rect::from_points(
+            self.canvas_to_screen((rect.left, rect.top)),
+            self.canvas_to_screen((rect.right, rect.bottom)),
+        ))
+    }
+
+    /// Nodes lying entirely inside of the frame, they are dragged along with it.
+    ///
+    /// Returns nothing if the node is not a frame.
+    pub fn frame_contents(&self, frame: NodeId) -> Vec<NodeId> {
+        let rect = match self.node(frame) {
+            Some(node) if node.is_frame() => node.bound_rect(),
+            _ => return Vec::new(),
+        };
+        let inside = |x: Rect| {
+            x.left >= rect.left
+                && x.right <= rect.right
+                && x.top >= rect.top
+                && x.bottom <= rect.bottom
+        };
+        let mut nodes = self
+            .index
+            .query_rect(rect)
+            .into_iter()
+            .filter_map(|item| match item {
+                SpatialItem::Node(id) if id != frame => Some(id),
+                _ => None,
+            })
+            .filter(|&id| inside(self.node(id).unwrap().bound_rect()))
+            .collect::<Vec<_>>();
+        nodes.sort_unstable_by_key(|id| id.index);
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::{tests::function, CastResult};
+
+    use super::*;
+
+    #[test]
+    fn frames_and_notes() {
+        let mut tree = Tree::new();
+        let inside = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let rect = tree.frame_rect(&[inside]).unwrap();
+        // Sticks out of the frame over its right edge.
+        let outside = tree.create_node(function("input_geo"), (rect.right - 10.0, 0.0).into());
+
+        let frame = tree.create_frame("inputs".to_string(), rect);
+        assert!(tree.node(outside).unwrap().bound_rect().left < rect.right);
+        assert!(!tree.frame_contents(frame).contains(&outside));
+        assert_eq!(tree.frame_contents(frame), vec![inside]);
+
+        // Nodes are on top of the frame, empty space inside of it is not hit.
+        assert!(matches!(tree.point_cast((0.0, 0.0).into()), CastResult::Node(x) if x == inside));
+        assert!(matches!(
+            tree.point_cast((0.0, 80.0).into()),
+            CastResult::None
+        ));
+        let title = (rect.left + 10.0, rect.top + 10.0).into();
+        assert!(matches!(tree.point_cast(title), CastResult::Node(x) if x == frame));
+
+        let note = tree.create_note("one\ntwo".to_string(), (300.0, 300.0).into());
+        assert!(matches!(tree.point_cast((310.0, 310.0).into()), CastResult::Node(x) if x == note));
+        assert_eq!(
+            tree.set_text(note, "one".to_string()).as_deref(),
+            Some("one\ntwo")
+        );
+        assert_eq!(tree.text(note), Some("one"));
+        assert!(matches!(
+            tree.point_cast((310.0, 360.0).into()),
+            CastResult::None
+        ));
+        assert_eq!(tree.set_text(inside, "input".to_string()), None);
+
+        let loaded = Tree::load(&tree.save()).unwrap();
+        assert_eq!(loaded.save(), tree.save());
+        let frame = loaded.nodes().find(|(_, x)| x.is_frame()).unwrap().0;
+        assert_eq!(loaded.text(frame), Some("inputs"));
+        assert_eq!(loaded.frame_contents(frame).len(), 1);
+    }
+}
@@ -0,0 +1,655 @@
+//! Versioned text representation of the [`Tree`].
+//!
+//! Document is a plain JSON object, so it can be stored anywhere strings can:
+//! local storage, files, clipboard etc.
+//!
+//! Nodes are referenced by their index in the `nodes` array and sockets by their
+//! index among the node's inputs or outputs, so documents stay valid no matter how
+//! sockets are laid out in memory.
+//!
+//! Group nodes store their inner tree in the same format, nested in the node.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    function::FunctionDefinition,
+    math::{Matrix, Point},
+    params::{Param, ParamType},
+};
+
+use super::{
+    AsSocketId, ConnectionError, Frame, Group, NodeData, NodeId, NodeKind, SocketId, SocketKind,
+    Tree,
+};
+
+/// Version written to every saved document.
+///
+/// Bump it each time the format changes in a way older code can't read.
+///
+/// - 1: initial version.
+/// - 2: group nodes.
+/// - 3: reroute knots.
+/// - 4: comment frames and notes.
+/// - 5: functions are referred to by namespaced names.
+/// - 6: values of the unconnected inputs.
+pub const DOCUMENT_VERSION: u32 = 6;
+
+#[derive(Debug)]
+pub enum DocumentError {
+    /// Text is not a valid document at all.
+    Syntax(serde_json::Error),
+    /// Document was saved by a newer version of the editor.
+    UnsupportedVersion(u32),
+    /// Node refers to a function that is not in the `FunctionRegistry`.
+    UnknownFunction { node: usize, name: String },
+    /// Group or reroute node has input or output of unknown type.
+    UnknownType { node: usize, name: String },
+    /// Value set on the input the node does not have, or of the wrong type.
+    InvalidValue { node: usize, input: usize },
+    /// Connection refers to a node that is not in the document.
+    UnknownNode { connection: usize, node: usize },
+    /// Connection refers to a socket the node's function does not have.
+    UnknownSocket {
+        connection: usize,
+        node: usize,
+        kind: SocketKind,
+        index: usize,
+    },
+    /// Sockets referred by connection can't be connected.
+    Connection {
+        connection: usize,
+        error: ConnectionError,
+    },
+}
+
+impl std::fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DocumentError::Syntax(err) => write!(f, "malformed document: {}", err),
+            DocumentError::UnsupportedVersion(version) => write!(
+                f,
+                "document version {} is not supported, latest supported version is {}",
+                version, DOCUMENT_VERSION
+            ),
+            DocumentError::UnknownFunction { node, name } => {
+                write!(f, "node #{} refers to unknown function `{}`", node, name)
+            }
+            DocumentError::UnknownType { node, name } => {
+                write!(f, "node #{} refers to unknown type `{}`", node, name)
+            }
+            DocumentError::InvalidValue { node, input } => {
+                write!(f, "node #{} has invalid value of input #{}", node, input)
+            }
+            DocumentError::UnknownNode { connection, node } => write!(
+                f,
+                "connection #{} refers to node #{} that does not exist",
+                connection, node
+            ),
+            DocumentError::UnknownSocket {
+                connection,
+                node,
+                kind,
+                index,
+            } => write!(
+                f,
+                "connection #{} refers to {:?} socket #{} that node #{} does not have",
+                connection, kind, index, node
+            ),
+            DocumentError::Connection { connection, error } => {
+                write!(f, "connection #{} is invalid: {}", connection, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DocumentError {}
+
+impl From<serde_json::Error> for DocumentError {
+    fn from(err: serde_json::Error) -> Self {
+        DocumentError::Syntax(err)
+    }
+}
+
+/// Only the version is read first, so documents of other versions
+/// are rejected before we try to make sense of the rest of them.
+#[derive(Deserialize)]
+struct DocumentHeader {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    version: u32,
+    #[serde(flatten)]
+    tree: TreeDocument,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TreeDocument {
+    transform: [[f64; 2]; 3],
+    nodes: Vec<NodeDocument>,
+    connections: Vec<ConnectionDocument>,
+}
+
+/// Nodes copied to the clipboard.
+#[derive(Serialize, Deserialize)]
+struct FragmentDocument {
+    version: u32,
+    nodes: Vec<NodeDocument>,
+    connections: Vec<ConnectionDocument>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NodeDocument {
+    /// Function name, group name for group nodes or title for frames.
+    function: String,
+    position: [f64; 2],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<GroupDocument>,
+    /// Type passed through, for reroute knots only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reroute: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    frame: Option<FrameDocument>,
+    /// Text of the sticky note.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    /// Values set on the unconnected inputs of the function node, by input.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    values: Vec<Option<Param>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FrameDocument {
+    color: String,
+    size: [f64; 2],
+}
+
+#[derive(Serialize, Deserialize)]
+struct GroupDocument {
+    tree: TreeDocument,
+    inputs: Vec<GroupInputDocument>,
+    outputs: Vec<GroupOutputDocument>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GroupInputDocument {
+    #[serde(rename = "type")]
+    ty: String,
+    targets: Vec<SocketDocument>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GroupOutputDocument {
+    #[serde(rename = "type")]
+    ty: String,
+    source: Option<SocketDocument>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ConnectionDocument {
+    input: SocketDocument,
+    output: SocketDocument,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SocketDocument {
+    node: usize,
+    index: usize,
+}
+
+impl Tree {
+    /// Serializes tree to the latest version of the document format.
+    pub fn save(&self) -> String {
+        let document = Document {
+            version: DOCUMENT_VERSION,
+            tree: self.document(),
+        };
+
+        serde_json::to_string_pretty(&document).expect("tree is always serializable")
+    }
+
+    /// Restores tree from the document created by [`Tree::save`].
+    pub fn load(text: &str) -> Result<Tree, DocumentError> {
+        let header: DocumentHeader = serde_json::from_str(text)?;
+        if header.version > DOCUMENT_VERSION {
+            return Err(DocumentError::UnsupportedVersion(header.version));
+        }
+
+        let document: Document = serde_json::from_str(text)?;
+        Tree::from_document(&document.tree)
+    }
+
+    fn document(&self) -> TreeDocument {
+        let ids = self.nodes().map(|(id, _)| id).collect::<Vec<_>>();
+        let (nodes, connections) = self.document_nodes(&ids, Point::default());
+        TreeDocument {
+            transform: self.transform.into(),
+            nodes,
+            connections,
+        }
+    }
+
+    /// Serializes nodes with positions relative to the `origin` along with
+    /// connections between them.
+    fn document_nodes(
+        &self,
+        ids: &[NodeId],
+        origin: Point,
+    ) -> (Vec<NodeDocument>, Vec<ConnectionDocument>) {
+        // Removed nodes leave holes in ids, documents use dense indices instead.
+        let indices = ids
+            .iter()
+            .enumerate()
+            .map(|(i, &id)| (id, i))
+            .collect::<HashMap<_, _>>();
+        let socket = |socket: SocketId| SocketDocument {
+            node: indices[&socket.node()],
+            index: self.node(socket.node()).unwrap().socket_index(socket.id()),
+        };
+
+        let nodes = ids
+            .iter()
+            .map(|&id| {
+                let node = self.node(id).unwrap();
+                NodeDocument {
+                    function: match &node.kind {
+                        NodeKind::Function(function) => function.name.to_string(),
+                        kind => kind.name().to_string(),
+                    },
+                    position: (node.position - origin).into(),
+                    group: match &node.kind {
+                        NodeKind::Group(group) => Some(group.document()),
+                        _ => None,
+                    },
+                    reroute: match node.kind {
+                        NodeKind::Reroute(ty) => Some(ty.to_string()),
+                        _ => None,
+                    },
+                    frame: match &node.kind {
+                        NodeKind::Frame(frame) => Some(FrameDocument {
+                            color: frame.color.clone(),
+                            size: [frame.size.width, frame.size.height],
+                        }),
+                        _ => None,
+                    },
+                    note: match &node.kind {
+                        NodeKind::Note(text) => Some(text.clone()),
+                        _ => None,
+                    },
+                    values: node.values.clone(),
+                }
+            })
+            .collect();
+        let connections = self
+            .connections
+            .iter()
+            .filter(|x| {
+                indices.contains_key(&x.input.node()) && indices.contains_key(&x.output.node())
+            })
+            .map(|connection| ConnectionDocument {
+                input: socket(connection.input.into_generic()),
+                output: socket(connection.output.into_generic()),
+            })
+            .collect();
+
+        (nodes, connections)
+    }
+
+    /// Serializes nodes and connections between them, so they can be pasted
+    /// with [`Tree::load_fragment`] into any tree.
+    ///
+    /// Node positions are stored relative to the center of the nodes.
+    pub fn save_fragment(&self, nodes: &[NodeId]) -> String {
+        let ids = nodes
+            .iter()
+            .copied()
+            .filter(|&x| self.node(x).is_some())
+            .collect::<Vec<_>>();
+        let center = ids
+            .iter()
+            .map(|&x| self.node(x).unwrap().position.to_vector())
+            .fold(Point::default(), |sum, x| sum + x / ids.len() as f64);
+        let (nodes, connections) = self.document_nodes(&ids, center);
+
+        let fragment = FragmentDocument {
+            version: DOCUMENT_VERSION,
+            nodes,
+            connections,
+        };
+        serde_json::to_string_pretty(&fragment).expect("tree is always serializable")
+    }
+
+    /// Restores fragment created by [`Tree::save_fragment`] as a separate tree,
+    /// see [`Tree::paste`].
+    pub fn load_fragment(text: &str) -> Result<Tree, DocumentError> {
+        let header: DocumentHeader = serde_json::from_str(text)?;
+        if header.version > DOCUMENT_VERSION {
+            return Err(DocumentError::UnsupportedVersion(header.version));
+        }
+
+        let fragment: FragmentDocument = serde_json::from_str(text)?;
+        Tree::from_document(&TreeDocument {
+            transform: Matrix::default().into(),
+            nodes: fragment.nodes,
+            connections: fragment.connections,
+        })
+    }
+
+    fn from_document(document: &TreeDocument) -> Result<Tree, DocumentError> {
+        let mut tree = Tree::new();
+        let mut ids = Vec::with_capacity(document.nodes.len());
+        for (i, node) in document.nodes.iter().enumerate() {
+            let mut data = match (&node.group, &node.reroute) {
+                _ if node.frame.is_some() || node.note.is_some() => {
+                    NodeData::new(Tree::annotation_from_document(node))
+                }
+                (Some(group), _) => NodeData::new(Group::from_document(i, &node.function, group)?),
+                (None, Some(ty)) => {
+                    let ty = ParamType::find(ty).ok_or_else(|| DocumentError::UnknownType {
+                        node: i,
+                        name: ty.clone(),
+                    })?;
+                    NodeData::new(NodeKind::Reroute(ty))
+                }
+                (None, None) => {
+                    let function = FunctionDefinition::find(&node.function).ok_or_else(|| {
+                        DocumentError::UnknownFunction {
+                            node: i,
+                            name: node.function.clone(),
+                        }
+                    })?;
+                    NodeData::new(function.clone())
+                }
+            };
+            data.position = node.position.into();
+            let id = tree.insert_node(data);
+            for (index, value) in node.values.iter().enumerate() {
+                let input = (id, index, SocketKind::Input).into_input();
+                if value.is_some() && tree.set_input_value(input, value.clone()).is_none() {
+                    return Err(DocumentError::InvalidValue {
+                        node: i,
+                        input: index,
+                    });
+                }
+            }
+            ids.push(id);
+        }
+
+        for (i, connection) in document.connections.iter().enumerate() {
+            let input = tree.document_socket(&ids, i, &connection.input, SocketKind::Input)?;
+            let output = tree.document_socket(&ids, i, &connection.output, SocketKind::Output)?;
+            tree.create_connection(input, output)
+                .map_err(|error| DocumentError::Connection {
+                    connection: i,
+                    error,
+                })?;
+        }
+
+        tree.transform = document.transform.into();
+        Ok(tree)
+    }
+
+    fn annotation_from_document(node: &NodeDocument) -> NodeKind {
+        match (&node.frame, &node.note) {
+            (Some(frame), _) => NodeKind::Frame(Box::new(Frame {
+                title: node.function.clone(),
+                color: frame.color.clone(),
+                size: (frame.size[0], frame.size[1]).into(),
+            })),
+            (None, note) => NodeKind::Note(note.clone().unwrap_or_default()),
+        }
+    }
+
+    fn document_socket(
+        &self,
+        ids: &[NodeId],
+        connection: usize,
+        socket: &SocketDocument,
+        kind: SocketKind,
+    ) -> Result<SocketId, DocumentError> {
+        let node = ids
+            .get(socket.node)
+            .copied()
+            .ok_or(DocumentError::UnknownNode {
+                connection,
+                node: socket.node,
+            })?;
+
+        let id = self
+            .node(node)
+            .and_then(|x| x.socket_id(kind, socket.index))
+            .ok_or(DocumentError::UnknownSocket {
+                connection,
+                node: socket.node,
+                kind,
+                index: socket.index,
+            })?;
+
+        Ok((node, id, kind).into_generic())
+    }
+}
+
+impl Group {
+    fn document(&self) -> GroupDocument {
+        let indices = self
+            .tree
+            .nodes()
+            .enumerate()
+            .map(|(i, (id, _))| (id, i))
+            .collect::<HashMap<_, _>>();
+        let socket = |socket: SocketId| SocketDocument {
+            node: indices[&socket.node()],
+            index: self
+                .tree
+                .node(socket.node())
+                .unwrap()
+                .socket_index(socket.id()),
+        };
+
+        GroupDocument {
+            tree: self.tree.document(),
+            inputs: (0..self.inputs.len())
+                .map(|i| GroupInputDocument {
+                    ty: self.inputs[i].to_string(),
+                    targets: self
+                        .input_targets(i)
+                        .map(|x| socket(x.into_generic()))
+                        .collect(),
+                })
+                .collect(),
+            outputs: (0..self.outputs.len())
+                .map(|i| GroupOutputDocument {
+                    ty: self.outputs[i].to_string(),
+                    source: self.output_source(i).map(|x| socket(x.into_generic())),
+                })
+                .collect(),
+        }
+    }
+
+    /// Sockets of the group interface missing in the inner tree are dropped,
+    /// the same way they are ignored when inner nodes are removed.
+    fn from_document(
+        node: usize,
+        name: &str,
+        document: &GroupDocument,
+    ) -> Result<Group, DocumentError> {
+        let tree = Tree::from_document(&document.tree)?;
+        let ids = tree.nodes().map(|(id, _)| id).collect::<Vec<_>>();
+        let socket =
+            |socket: &SocketDocument, kind| tree.document_socket(&ids, 0, socket, kind).ok();
+        let ty = |name: &str| {
+            ParamType::find(name).ok_or_else(|| DocumentError::UnknownType {
+                node,
+                name: name.to_string(),
+            })
+        };
+
+        Ok(Group {
+            name: name.to_string(),
+            inputs: document
+                .inputs
+                .iter()
+                .map(|x| ty(&x.ty))
+                .collect::<Result<_, _>>()?,
+            outputs: document
+                .outputs
+                .iter()
+                .map(|x| ty(&x.ty))
+                .collect::<Result<_, _>>()?,
+            input_targets: document
+                .inputs
+                .iter()
+                .map(|x| {
+                    x.targets
+                        .iter()
+                        .filter_map(|x| socket(x, SocketKind::Input))
+                        .map(|x| x.into_input())
+                        .collect()
+                })
+                .collect(),
+            output_sources: document
+                .outputs
+                .iter()
+                .map(|x| {
+                    x.source
+                        .as_ref()
+                        .and_then(|x| socket(x, SocketKind::Output))
+                        .map(|x| x.into_output())
+                })
+                .collect(),
+            tree,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::tests::function;
+
+    #[test]
+    fn save_and_load() {
+        let mut tree = Tree::new();
+        let removed = tree.create_node(function("output_geo"), (0.0, 0.0).into());
+        let input = tree.create_node(function("input_geo"), (10.0, 20.0).into());
+        let boolean = tree.create_node(function("boolean"), (30.0, 40.0).into());
+        tree.create_connection(
+            (input, 0, SocketKind::Output),
+            (boolean, 1, SocketKind::Input),
+        )
+        .unwrap();
+        tree.remove_node(removed);
+        let value = Some(Param::f64(2.0));
+        tree.set_input_value((boolean, 0, SocketKind::Input).into_input(), value);
+
+        let loaded = Tree::load(&tree.save()).unwrap();
+
+        let nodes = loaded.nodes().map(|(_, x)| x).collect::<Vec<_>>();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[1].kind.name(), "boolean");
+        assert_eq!(nodes[1].position, (30.0, 40.0).into());
+        assert_eq!(nodes[1].input_value(0), Some(&Param::f64(2.0)));
+        assert_eq!(loaded.connections.len(), 1);
+        assert_eq!(loaded.save(), tree.save());
+    }
+
+    #[test]
+    fn save_and_load_group() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let boolean = tree.create_node(function("boolean"), (0.0, 100.0).into());
+        let output = tree.create_node(function("output_geo"), (0.0, 200.0).into());
+        tree.create_connection(
+            (input, 0, SocketKind::Output),
+            (boolean, 0, SocketKind::Input),
+        )
+        .unwrap();
+        tree.create_connection(
+            (boolean, 2, SocketKind::Output),
+            (output, 0, SocketKind::Input),
+        )
+        .unwrap();
+        let (group, _) = tree.collapse(&[boolean]).unwrap();
+
+        let loaded = Tree::load(&tree.save()).unwrap();
+        let loaded_group = loaded.nodes().find_map(|(id, _)| loaded.group(id)).unwrap();
+        let group = tree.group(group).unwrap();
+        assert_eq!(loaded_group.inputs, group.inputs);
+        assert_eq!(loaded_group.outputs, group.outputs);
+        assert_eq!(loaded_group.input_targets(0).count(), 1);
+        assert!(loaded_group.output_source(0).is_some());
+        assert_eq!(loaded.connections.len(), 2);
+        assert_eq!(loaded.save(), tree.save());
+    }
+
+    #[test]
+    fn save_and_load_reroute() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (0.0, 200.0).into());
+        let target = (output, 0, SocketKind::Input).into_input();
+        tree.create_connection((input, 0, SocketKind::Output), target)
+            .unwrap();
+        tree.insert_reroute(target, (100.0, 100.0).into()).unwrap();
+
+        let loaded = Tree::load(&tree.save()).unwrap();
+        let knot = loaded
+            .nodes()
+            .find(|(_, x)| matches!(x.kind, NodeKind::Reroute(ParamType::f64)));
+        assert!(knot.is_some());
+        assert_eq!(loaded.connections.len(), 2);
+        assert_eq!(loaded.save(), tree.save());
+    }
+
+    #[test]
+    fn fragment() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let boolean = tree.create_node(function("boolean"), (100.0, 100.0).into());
+        let output = tree.create_node(function("output_geo"), (0.0, 200.0).into());
+        tree.create_connection(
+            (input, 0, SocketKind::Output),
+            (boolean, 0, SocketKind::Input),
+        )
+        .unwrap();
+        tree.create_connection(
+            (boolean, 2, SocketKind::Output),
+            (output, 0, SocketKind::Input),
+        )
+        .unwrap();
+
+        let fragment = Tree::load_fragment(&tree.save_fragment(&[boolean, output])).unwrap();
+        let nodes = fragment.nodes().map(|(_, x)| x).collect::<Vec<_>>();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].position, (50.0, -50.0).into());
+        assert_eq!(nodes[1].position, (-50.0, 50.0).into());
+        // Connection from the node left behind is not copied.
+        assert_eq!(fragment.connections.len(), 1);
+    }
+
+    #[test]
+    fn unknown_function() {
+        let text = r#"{
+            "version": 1,
+            "transform": [[1.0, 0.0], [0.0, 1.0], [0.0, 0.0]],
+            "nodes": [{ "function": "missing", "position": [0.0, 0.0] }],
+            "connections": []
+        }"#;
+
+        match Tree::load(text) {
+            Err(DocumentError::UnknownFunction { node: 0, name }) => assert_eq!(name, "missing"),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn newer_version() {
+        assert!(matches!(
+            Tree::load(r#"{ "version": 999 }"#),
+            Err(DocumentError::UnsupportedVersion(999))
+        ));
+    }
+}
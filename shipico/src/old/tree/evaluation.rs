@@ -0,0 +1,839 @@
+//! Running the tree: nodes are called in topological order and every output
+//! socket gets its own slot in the memory functions read inputs from.
+//!
+//! Tree is lowered to a list of function calls first: groups are inlined and
+//! reroute knots disappear. [`Executor`] then runs the calls and keeps their
+//! outputs between runs. Call is computed again only if the values of its
+//! inputs changed, so after an edit only the nodes downstream of it are, and
+//! only as far as their inputs actually change.
+//!
+//! Function that fails does not stop the run: nodes downstream of it are
+//! skipped and the failure is reported as a [`Diagnostic`] of the node.
+//!
+//! On native targets calls that don't depend on each other run on several
+//! threads, started along with the executor and kept for all its runs.
+//! Functions are pure and every value has its own slot, so results are the
+//! same no matter how many threads there are.
+
+use std::{
+    collections::{HashMap, HashSet},
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    function::{FunctionDefinition, FunctionError},
+    params::Param,
+};
+
+use super::{
+    AsSocketId, Diagnostic, InputSocketId, NodeId, NodeKind, OutputSocketId, Problem, Severity,
+    SocketKind, Tree,
+};
+
+/// Values of the output sockets computed by [`Tree::evaluate`].
+pub struct Evaluation {
+    /// Values downstream of the failed calls are missing.
+    memory: Vec<Option<Param>>,
+    addresses: Addresses,
+    failures: Vec<Diagnostic>,
+}
+
+/// Evaluates trees reusing the outputs computed by the previous runs.
+pub struct Executor {
+    /// Outputs of the calls along with the inputs they were computed from,
+    /// by the path to the function node.
+    cache: HashMap<Vec<NodeId>, CachedCall>,
+    counters: Counters,
+    /// `None` if calls run one by one.
+    workers: Option<Workers>,
+}
+
+/// Call sent to the workers: its index, the function and the inputs.
+type Job = (usize, FunctionDefinition, Vec<Param>);
+
+/// Threads computing the calls of the parallel runs.
+struct Workers {
+    /// Closed when the executor is dropped, that stops the threads.
+    jobs: Option<mpsc::Sender<Job>>,
+    /// Outcomes by the index of the call, `Err` if the function panicked.
+    done: mpsc::Receiver<(usize, thread::Result<Outcome>)>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+/// How much work the last run of the [`Executor`] did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// Function nodes that were called.
+    pub computed: usize,
+    /// Function nodes whose outputs were taken from the cache.
+    pub reused: usize,
+    /// Function nodes that were called and failed.
+    pub failed: usize,
+    /// Function nodes downstream of the failed ones, they were not called.
+    pub skipped: usize,
+}
+
+#[derive(Clone)]
+struct CachedCall {
+    inputs: Vec<Param>,
+    outputs: Vec<Param>,
+}
+
+/// What happened to the call during the run.
+#[derive(Clone)]
+enum Outcome {
+    Computed(CachedCall),
+    Reused(CachedCall),
+    Failed(FunctionError),
+    /// Some of the inputs have no value, because a call upstream failed.
+    Skipped,
+}
+
+impl Outcome {
+    fn outputs(&self) -> &[Param] {
+        match self {
+            Outcome::Computed(call) | Outcome::Reused(call) => &call.outputs,
+            Outcome::Failed(_) | Outcome::Skipped => &[],
+        }
+    }
+}
+
+/// Where the values of the output sockets are in the memory.
+#[derive(Default)]
+pub(super) struct Addresses {
+    pub(super) outputs: HashMap<OutputSocketId, usize>,
+    /// Addresses of the inner tree of each group node.
+    groups: HashMap<NodeId, Addresses>,
+}
+
+/// Call of the function node, values are numbered in the order they are
+/// computed and every value is computed by exactly one call.
+pub(super) struct Call {
+    /// Groups the node is in followed by the node itself.
+    pub(super) node: Vec<NodeId>,
+    pub(super) function: FunctionDefinition,
+    pub(super) inputs: Vec<usize>,
+    pub(super) outputs: Vec<usize>,
+}
+
+/// Tree lowered to the function calls, see [`Tree::lower`].
+pub(super) struct Lowered {
+    /// Calls in topological order.
+    pub(super) calls: Vec<Call>,
+    /// Number of the values calls compute, along with the constants.
+    pub(super) values: usize,
+    /// Values of the unconnected inputs, no call computes them.
+    pub(super) constants: Vec<(usize, Param)>,
+    pub(super) addresses: Addresses,
+}
+
+impl Lowered {
+    /// Memory with only the constants in it.
+    fn memory(&self) -> Vec<Option<Param>> {
+        let mut memory = vec![None; self.values];
+        for (value, constant) in &self.constants {
+            memory[*value] = Some(constant.clone());
+        }
+        memory
+    }
+
+    /// Indices of the calls computing the inputs of each call, constants
+    /// have no producers.
+    fn dependencies(&self) -> Vec<Vec<usize>> {
+        let mut producers = vec![None; self.values];
+        for (index, call) in self.calls.iter().enumerate() {
+            call.outputs
+                .iter()
+                .for_each(|&x| producers[x] = Some(index));
+        }
+        self.calls
+            .iter()
+            .map(|call| {
+                let mut producers = call
+                    .inputs
+                    .iter()
+                    .filter_map(|&x| producers[x])
+                    .collect::<Vec<_>>();
+                producers.sort_unstable();
+                producers.dedup();
+                producers
+            })
+            .collect()
+    }
+
+    /// Largest number of the calls at the same depth of the dependency
+    /// graph. They don't depend on each other, so it's 1 if there is
+    /// nothing to run in parallel.
+    fn width(&self) -> usize {
+        let mut depths = Vec::<usize>::with_capacity(self.calls.len());
+        let mut widths = Vec::new();
+        for producers in self.dependencies() {
+            let depth = producers.iter().map(|&x| depths[x] + 1).max().unwrap_or(0);
+            if depth == widths.len() {
+                widths.push(0);
+            }
+            widths[depth] += 1;
+            depths.push(depth);
+        }
+        widths.into_iter().max().unwrap_or(0)
+    }
+}
+
+impl Evaluation {
+    /// Value of the output socket of the tree at the `path`, `None` if there
+    /// is no such socket or its node failed or was skipped.
+    pub fn value(&self, path: &[NodeId], output: OutputSocketId) -> Option<&Param> {
+        let addresses = path
+            .iter()
+            .try_fold(&self.addresses, |x, node| x.groups.get(node))?;
+        self.memory[*addresses.outputs.get(&output)?].as_ref()
+    }
+
+    /// Values of all the computed output sockets of the evaluated tree.
+    pub fn values(&self) -> impl Iterator<Item = (OutputSocketId, &Param)> {
+        self.addresses
+            .outputs
+            .iter()
+            .filter_map(move |(&output, &address)| Some((output, self.memory[address].as_ref()?)))
+    }
+
+    /// Nodes that failed, with the input that had a wrong value if that's
+    /// why they did.
+    pub fn failures(&self) -> &[Diagnostic] {
+        &self.failures
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::with_threads(default_threads())
+    }
+}
+
+impl Workers {
+    fn new(threads: usize) -> Workers {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (sender, done) = mpsc::channel();
+        let queue = Arc::new(Mutex::new(queue));
+        let threads = (0..threads)
+            .map(|_| {
+                let queue = queue.clone();
+                let done = sender.clone();
+                thread::spawn(move || loop {
+                    // Queue is unlocked as soon as the job is taken.
+                    let job = queue.lock().unwrap().recv();
+                    let (index, function, inputs) = match job {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+                    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                        Executor::compute(&function, inputs)
+                    }));
+                    if done.send((index, outcome)).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        Workers {
+            jobs: Some(jobs),
+            done,
+            threads,
+        }
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        self.jobs = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |x| x.get())
+}
+
+/// There are no threads in the browser.
+#[cfg(target_arch = "wasm32")]
+fn default_threads() -> usize {
+    1
+}
+
+impl Executor {
+    /// Executor using all the available cores.
+    pub fn new() -> Executor {
+        Executor::default()
+    }
+
+    /// Executor running independent calls on `threads` threads, they are
+    /// started here and stopped when the executor is dropped.
+    ///
+    /// Threads are ignored on wasm, calls always run one by one there.
+    pub fn with_threads(threads: usize) -> Executor {
+        let parallel = threads > 1 && cfg!(not(target_arch = "wasm32"));
+        Executor {
+            cache: HashMap::new(),
+            counters: Counters::default(),
+            workers: parallel.then_some(threads).map(Workers::new),
+        }
+    }
+
+    /// Computes values of all the output sockets of the tree, see
+    /// [`Tree::evaluate`].
+    ///
+    /// Nodes are identified by their ids, so the executor should always run
+    /// the same tree, or its edited versions.
+    pub fn run(&mut self, tree: &Tree) -> Result<Evaluation, Vec<Diagnostic>> {
+        let errors = tree
+            .validate()
+            .into_iter()
+            .filter(|x| x.severity == Severity::Error)
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let lowered = tree.lower();
+        // Threads don't help if every call depends on the previous one.
+        let results = match &self.workers {
+            Some(workers) if lowered.width() > 1 => self.execute_parallel(&lowered, workers),
+            _ => self.execute(&lowered),
+        };
+
+        // Results come in the order of the calls whatever thread computed
+        // them, so the cache and the counters don't depend on the threads.
+        self.counters = Counters::default();
+        let mut memory = lowered.memory();
+        let mut failures = Vec::new();
+        for (call, outcome) in lowered.calls.iter().zip(results) {
+            for (&value, output) in call.outputs.iter().zip(outcome.outputs()) {
+                memory[value] = Some(output.clone());
+            }
+            match outcome {
+                Outcome::Computed(result) => {
+                    self.counters.computed += 1;
+                    self.cache.insert(call.node.clone(), result);
+                }
+                Outcome::Reused(_) => self.counters.reused += 1,
+                Outcome::Failed(error) => {
+                    self.counters.failed += 1;
+                    self.cache.remove(&call.node);
+                    let (&node, path) = call.node.split_last().unwrap();
+                    failures.push(Diagnostic {
+                        severity: Severity::Error,
+                        path: path.to_vec(),
+                        node,
+                        socket: error
+                            .input
+                            .map(|x| (node, x, SocketKind::Input).into_generic()),
+                        problem: Problem::Failed {
+                            message: error.message,
+                        },
+                    });
+                }
+                Outcome::Skipped => self.counters.skipped += 1,
+            }
+        }
+        let nodes = lowered
+            .calls
+            .iter()
+            .map(|x| &x.node)
+            .collect::<HashSet<_>>();
+        self.cache.retain(|node, _| nodes.contains(node));
+
+        Ok(Evaluation {
+            memory,
+            addresses: lowered.addresses,
+            failures,
+        })
+    }
+
+    /// Work done by the last successful run.
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    /// Computes the call or takes its outputs from the cache if the inputs
+    /// did not change. Inputs are `None` if some of them have no value.
+    fn call(&self, call: &Call, inputs: Option<Vec<Param>>) -> Outcome {
+        match self.cached(call, inputs.as_deref()) {
+            Some(outcome) => outcome,
+            None => Executor::compute(&call.function, inputs.unwrap()),
+        }
+    }
+
+    /// Outcome of the call that doesn't need computing, `None` if it does.
+    fn cached(&self, call: &Call, inputs: Option<&[Param]>) -> Option<Outcome> {
+        let inputs = match inputs {
+            Some(inputs) => inputs,
+            None => return Some(Outcome::Skipped),
+        };
+        match self.cache.get(&call.node) {
+            Some(cached) if cached.inputs == inputs => Some(Outcome::Reused(cached.clone())),
+            _ => None,
+        }
+    }
+
+    fn compute(function: &FunctionDefinition, inputs: Vec<Param>) -> Outcome {
+        let mut memory = inputs.clone();
+        let addresses = (0..inputs.len()).collect::<Vec<_>>();
+        match function.call(&addresses, &mut memory) {
+            Ok((start, _)) => {
+                memory.drain(..start);
+                let outputs = memory;
+                Outcome::Computed(CachedCall { inputs, outputs })
+            }
+            Err(error) => Outcome::Failed(error),
+        }
+    }
+
+    /// Runs the calls one by one.
+    fn execute(&self, lowered: &Lowered) -> Vec<Outcome> {
+        let mut memory = lowered.memory();
+        let mut results = Vec::with_capacity(lowered.calls.len());
+        for call in &lowered.calls {
+            let inputs = call.inputs.iter().map(|&x| memory[x].clone());
+            let outcome = self.call(call, inputs.collect());
+            for (&value, output) in call.outputs.iter().zip(outcome.outputs()) {
+                memory[value] = Some(output.clone());
+            }
+            results.push(outcome);
+        }
+        results
+    }
+
+    /// Runs the calls on the workers, every call starts as soon as all the
+    /// calls computing its inputs are done. Cached and skipped calls are
+    /// not sent to the workers.
+    fn execute_parallel(&self, lowered: &Lowered, workers: &Workers) -> Vec<Outcome> {
+        let calls = &lowered.calls;
+        let mut consumers = vec![Vec::new(); calls.len()];
+        let mut pending = vec![0; calls.len()];
+        for (index, producers) in lowered.dependencies().into_iter().enumerate() {
+            pending[index] = producers.len();
+            producers.into_iter().for_each(|x| consumers[x].push(index));
+        }
+        let mut ready = (0..calls.len())
+            .filter(|&x| pending[x] == 0)
+            .collect::<Vec<_>>();
+        ready.reverse();
+
+        let jobs = workers.jobs.as_ref().unwrap();
+        let mut memory = lowered.memory();
+        let mut results = vec![None; calls.len()];
+        // Number of the calls that are not done yet, and of those the
+        // workers are computing.
+        let mut remaining = calls.len();
+        let mut running = 0;
+        while remaining > 0 {
+            let (index, outcome) = match ready.pop() {
+                Some(index) => {
+                    let call = &calls[index];
+                    let inputs = call.inputs.iter().map(|&x| memory[x].clone());
+                    let inputs = inputs.collect::<Option<Vec<_>>>();
+                    match self.cached(call, inputs.as_deref()) {
+                        Some(outcome) => (index, outcome),
+                        None => {
+                            let job = (index, call.function.clone(), inputs.unwrap());
+                            jobs.send(job).unwrap();
+                            running += 1;
+                            continue;
+                        }
+                    }
+                }
+                None => {
+                    running -= 1;
+                    match workers.done.recv().unwrap() {
+                        (index, Ok(outcome)) => (index, outcome),
+                        (_, Err(payload)) => {
+                            // Jobs still running would end up in the next run.
+                            for _ in 0..running {
+                                let _ = workers.done.recv();
+                            }
+                            panic::resume_unwind(payload);
+                        }
+                    }
+                }
+            };
+            for (&value, output) in calls[index].outputs.iter().zip(outcome.outputs()) {
+                memory[value] = Some(output.clone());
+            }
+            for &consumer in &consumers[index] {
+                pending[consumer] -= 1;
+                if pending[consumer] == 0 {
+                    ready.push(consumer);
+                }
+            }
+            results[index] = Some(outcome);
+            remaining -= 1;
+        }
+        results.into_iter().map(Option::unwrap).collect()
+    }
+}
+
+impl Tree {
+    /// Nodes in the order they can be computed in: every node comes after
+    /// the nodes connected to its inputs. Annotations are skipped.
+    pub(super) fn topological_order(&self) -> Vec<NodeId> {
+        let mut pending = self
+            .nodes()
+            .filter(|(_, node)| !node.is_annotation())
+            .map(|(id, _)| {
+                let inputs = self
+                    .connections
+                    .iter()
+                    .filter(|x| x.input.node() == id)
+                    .count();
+                (id, inputs)
+            })
+            .collect::<HashMap<_, _>>();
+        let mut ready = self
+            .nodes()
+            .map(|(id, _)| id)
+            .filter(|id| pending.get(id) == Some(&0))
+            .collect::<Vec<_>>();
+        ready.reverse();
+
+        let mut order = Vec::with_capacity(pending.len());
+        while let Some(node) = ready.pop() {
+            order.push(node);
+            for connection in self.connections.iter().filter(|x| x.output.node() == node) {
+                let inputs = pending.get_mut(&connection.input.node()).unwrap();
+                *inputs -= 1;
+                if *inputs == 0 {
+                    ready.push(connection.input.node());
+                }
+            }
+        }
+        assert_eq!(order.len(), pending.len(), "connections never form a cycle");
+        order
+    }
+
+    /// Computes values of all the output sockets.
+    ///
+    /// Tree is validated first, errors found by [`Tree::validate`] are
+    /// returned instead, so every input has a value of the right type.
+    ///
+    /// Calls run one by one, use an [`Executor`] to run them on threads and
+    /// reuse the outputs between runs.
+    pub fn evaluate(&self) -> Result<Evaluation, Vec<Diagnostic>> {
+        Executor::with_threads(1).run(self)
+    }
+
+    /// Lowers the valid tree to the function calls.
+    pub(super) fn lower(&self) -> Lowered {
+        let mut lowered = Lowered {
+            calls: Vec::new(),
+            values: 0,
+            constants: Vec::new(),
+            addresses: Addresses::default(),
+        };
+        lowered.addresses = self.lower_in(&mut Vec::new(), &HashMap::new(), &mut lowered);
+        lowered
+    }
+
+    /// Appends calls computing the tree at the `path` and returns values of
+    /// its outputs. Inputs in `fed` get the values of the group inputs.
+    fn lower_in(
+        &self,
+        path: &mut Vec<NodeId>,
+        fed: &HashMap<InputSocketId, usize>,
+        lowered: &mut Lowered,
+    ) -> Addresses {
+        let mut addresses = Addresses::default();
+        for id in self.topological_order() {
+            let node = self.node(id).unwrap();
+            let inputs = (0..node.kind.inputs().len())
+                .map(|index| {
+                    let input = (id, index, SocketKind::Input).into_input();
+                    match (self.connected_output(input), fed.get(&input)) {
+                        (Some(output), _) => addresses.outputs[&output],
+                        (None, Some(&value)) => value,
+                        (None, None) => {
+                            let constant = node.input_value(index).expect("tree is valid");
+                            lowered.constants.push((lowered.values, constant.clone()));
+                            lowered.values += 1;
+                            lowered.values - 1
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let produced = match &node.kind {
+                NodeKind::Function(function) => {
+                    let start = lowered.values;
+                    lowered.values += function.outputs.len();
+                    let outputs = (start..lowered.values).collect::<Vec<_>>();
+                    let mut node = path.clone();
+                    node.push(id);
+                    lowered.calls.push(Call {
+                        node,
+                        function: function.clone(),
+                        inputs,
+                        outputs: outputs.clone(),
+                    });
+                    outputs
+                }
+                NodeKind::Reroute(_) => inputs,
+                NodeKind::Group(group) => {
+                    let fed = (0..group.inputs.len())
+                        .flat_map(|x| group.input_targets(x).map(move |target| (target, x)))
+                        .map(|(target, x)| (target, inputs[x]))
+                        .collect();
+                    path.push(id);
+                    let inner = group.tree.lower_in(path, &fed, lowered);
+                    path.pop();
+                    let outputs = (0..group.outputs.len())
+                        .map(|x| inner.outputs[&group.output_source(x).unwrap()])
+                        .collect();
+                    addresses.groups.insert(id, inner);
+                    outputs
+                }
+                NodeKind::Frame(_) | NodeKind::Note(_) => unreachable!("annotations are skipped"),
+            };
+            for (index, value) in produced.into_iter().enumerate() {
+                let output = node.socket_id(SocketKind::Output, index).unwrap();
+                addresses
+                    .outputs
+                    .insert((id, output, SocketKind::Output).into_output(), value);
+            }
+        }
+        addresses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::tests::{function, socket};
+
+    use super::*;
+
+    #[test]
+    fn evaluation() {
+        let mut tree = Tree::new();
+        let output = tree.create_node(function("output_geo"), (600.0, 0.0).into());
+        let boolean = tree.create_node(function("boolean"), (300.0, 0.0).into());
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        assert!(tree.evaluate().is_err());
+
+        for index in 0..2 {
+            tree.create_connection(
+                socket(input, 0, SocketKind::Output),
+                socket(boolean, index, SocketKind::Input),
+            )
+            .unwrap();
+        }
+        tree.create_connection(
+            socket(boolean, 2, SocketKind::Output),
+            socket(output, 0, SocketKind::Input),
+        )
+        .unwrap();
+        assert_eq!(tree.topological_order(), [input, boolean, output]);
+        // Chain of calls runs one by one even with the threads.
+        assert_eq!(tree.lower().width(), 1);
+
+        let result = (boolean, 2, SocketKind::Output).into_output();
+        let evaluation = tree.evaluate().unwrap();
+        assert_eq!(evaluation.values().count(), 2);
+        assert_eq!(evaluation.value(&[], result), Some(&Param::f64(0.0)));
+
+        // Values computed inside of the group are there too.
+        let (group, _) = tree.collapse(&[boolean]).unwrap();
+        let evaluation = tree.evaluate().unwrap();
+        let inner = tree.group(group).unwrap().output_source(0).unwrap();
+        assert_eq!(evaluation.value(&[group], inner), Some(&Param::f64(0.0)));
+        let result = (group, 1, SocketKind::Output).into_output();
+        assert_eq!(evaluation.value(&[], result), Some(&Param::f64(0.0)));
+    }
+
+    #[test]
+    fn only_changed_nodes_are_computed() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let boolean = tree.create_node(function("boolean"), (300.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (600.0, 0.0).into());
+        for index in 0..2 {
+            tree.create_connection(
+                socket(input, 0, SocketKind::Output),
+                socket(boolean, index, SocketKind::Input),
+            )
+            .unwrap();
+        }
+        tree.create_connection(
+            socket(boolean, 2, SocketKind::Output),
+            socket(output, 0, SocketKind::Input),
+        )
+        .unwrap();
+
+        let mut executor = Executor::new();
+        let counters = |executor: &mut Executor, tree: &Tree| {
+            executor.run(tree).ok().unwrap();
+            executor.counters()
+        };
+        let expected = |computed, reused| Counters {
+            computed,
+            reused,
+            ..Counters::default()
+        };
+        assert_eq!(counters(&mut executor, &tree), expected(3, 0));
+        assert_eq!(counters(&mut executor, &tree), expected(0, 3));
+
+        // New node is computed, but its output is the same, so the nodes
+        // downstream of it are not.
+        let other = tree.create_node(function("input_geo"), (0.0, 300.0).into());
+        tree.create_connection(
+            socket(other, 0, SocketKind::Output),
+            socket(boolean, 0, SocketKind::Input),
+        )
+        .unwrap();
+        assert_eq!(counters(&mut executor, &tree), expected(1, 3));
+
+        // Inner nodes of the group are cached too.
+        tree.collapse(&[boolean]);
+        assert_eq!(counters(&mut executor, &tree), expected(1, 3));
+        assert_eq!(counters(&mut executor, &tree), expected(0, 4));
+    }
+
+    #[test]
+    fn threads_do_not_change_results() {
+        let mut tree = Tree::new();
+        let connect = |tree: &mut Tree, output, input| {
+            tree.create_connection(
+                socket(output, 2, SocketKind::Output),
+                socket(input, 0, SocketKind::Input),
+            )
+            .unwrap();
+        };
+        // Independent branches of booleans between one input and one output each.
+        let mut outputs = Vec::new();
+        for branch in 0..8 {
+            let y = branch as f64 * 300.0;
+            let input = tree.create_node(function("input_geo"), (0.0, y).into());
+            let mut last = tree.create_node(function("boolean"), (300.0, y).into());
+            for index in 0..2 {
+                tree.create_connection(
+                    socket(input, 0, SocketKind::Output),
+                    socket(last, index, SocketKind::Input),
+                )
+                .unwrap();
+            }
+            for i in 0..4 {
+                let boolean =
+                    tree.create_node(function("boolean"), (600.0 + 300.0 * i as f64, y).into());
+                connect(&mut tree, last, boolean);
+                tree.create_connection(
+                    socket(input, 0, SocketKind::Output),
+                    socket(boolean, 1, SocketKind::Input),
+                )
+                .unwrap();
+                last = boolean;
+            }
+            let output = tree.create_node(function("output_geo"), (2000.0, y).into());
+            connect(&mut tree, last, output);
+            outputs.push((last, 2, SocketKind::Output).into_output());
+        }
+
+        assert_eq!(tree.lower().width(), 8);
+
+        let run = |executor: &mut Executor| {
+            let evaluation = executor.run(&tree).ok().unwrap();
+            let values = outputs
+                .iter()
+                .map(|&x| evaluation.value(&[], x).unwrap().clone())
+                .collect::<Vec<_>>();
+            (values, executor.counters())
+        };
+        let (values, counters) = run(&mut Executor::with_threads(1));
+        assert_eq!(counters.computed, 8 * 7);
+        for threads in 2..6 {
+            let mut executor = Executor::with_threads(threads);
+            assert_eq!(run(&mut executor), (values.clone(), counters));
+            // Same workers run the calls again, all of them are cached now.
+            let reused = Counters {
+                reused: 8 * 7,
+                ..Counters::default()
+            };
+            assert_eq!(run(&mut executor), (values.clone(), reused));
+        }
+    }
+
+    #[test]
+    fn failures_skip_downstream_nodes() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let divide = tree.create_node(function("divide"), (300.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (600.0, 0.0).into());
+        for index in 0..2 {
+            tree.create_connection(
+                socket(input, 0, SocketKind::Output),
+                socket(divide, index, SocketKind::Input),
+            )
+            .unwrap();
+        }
+        tree.create_connection(
+            socket(divide, 2, SocketKind::Output),
+            socket(output, 0, SocketKind::Input),
+        )
+        .unwrap();
+
+        let mut executor = Executor::new();
+        let evaluation = executor.run(&tree).ok().unwrap();
+        let counters = executor.counters();
+        assert_eq!(
+            (counters.computed, counters.failed, counters.skipped),
+            (1, 1, 1)
+        );
+        let result = (divide, 2, SocketKind::Output).into_output();
+        assert_eq!(evaluation.value(&[], result), None);
+        assert_eq!(evaluation.failures().len(), 1);
+        assert_eq!(evaluation.failures()[0].node, divide);
+        assert_eq!(evaluation.failures()[0].socket, None);
+
+        // Values of the wrong type are reported along with the input.
+        let mut memory = vec![Param::i64(1), Param::f64(1.0)];
+        let error = function("divide").call(&[0, 1], &mut memory).unwrap_err();
+        assert_eq!(error.input, Some(0));
+        assert_eq!(memory.len(), 2);
+    }
+
+    #[test]
+    fn unconnected_inputs_get_values() {
+        let mut tree = Tree::new();
+        let lerp = tree.create_node(function("lerp"), (0.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (300.0, 0.0).into());
+        tree.create_connection(
+            (lerp, 3, SocketKind::Output).into_generic(),
+            (output, 0, SocketKind::Input).into_generic(),
+        )
+        .unwrap();
+        // `factor` has a default, `from` and `to` are required.
+        assert_eq!(tree.validate().len(), 2);
+
+        let input = |index| (lerp, index, SocketKind::Input).into_input();
+        tree.set_input_value(input(0), Some(Param::f64(2.0)));
+        tree.set_input_value(input(1), Some(Param::f64(4.0)));
+        let result = (lerp, 3, SocketKind::Output).into_output();
+        let mut executor = Executor::with_threads(4);
+        let evaluation = executor.run(&tree).unwrap();
+        assert_eq!(evaluation.value(&[], result), Some(&Param::f64(3.0)));
+
+        // Values are inputs like any other, changing them recomputes the node.
+        tree.set_input_value(input(2), Some(Param::f64(1.0)));
+        let evaluation = executor.run(&tree).unwrap();
+        assert_eq!(evaluation.value(&[], result), Some(&Param::f64(4.0)));
+        assert_eq!(executor.counters().computed, 2);
+
+        let program = tree.compile().unwrap();
+        let name = format!("output_geo#{}.geometry", output.index);
+        let results = vec![(name, Param::f64(4.0))];
+        assert_eq!(program.run().unwrap(), results);
+        let loaded = crate::tree::Program::load(&program.save()).unwrap();
+        assert_eq!(loaded.run().unwrap(), results);
+    }
+}
@@ -0,0 +1,247 @@
+//! Groups collapse part of the tree into a single node.
+//!
+//! Group keeps collapsed nodes in its own [`Tree`]. Connections that crossed
+//! the boundary of the collapsed nodes become inputs and outputs of the group,
+//! so from the outside group looks just like a function node.
+
+use std::collections::HashMap;
+
+use crate::{math::Point, params::ParamType};
+
+use super::{
+    AsSocketId, InputSocketId, NodeData, NodeId, NodeKind, OutputSocketId, RemovedNode, SocketKind,
+    Tree,
+};
+
+#[derive(Clone)]
+pub struct Group {
+    pub name: String,
+    pub(super) tree: Tree,
+    /// Types of the group inputs, like `FunctionDefinition::inputs`.
+    pub(super) inputs: Vec<ParamType>,
+    /// Types of the group outputs, like `FunctionDefinition::outputs`.
+    pub(super) outputs: Vec<ParamType>,
+    /// Inner sockets that receive the value of each group input.
+    pub(super) input_targets: Vec<Vec<InputSocketId>>,
+    /// Inner socket that provides the value of each group output.
+    pub(super) output_sources: Vec<Option<OutputSocketId>>,
+}
+
+impl Group {
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
+    /// Inner sockets that receive the value of the group input.
+    ///
+    /// Sockets of the inner nodes removed after collapsing are skipped.
+    pub fn input_targets(&self, input: usize) -> impl Iterator<Item = InputSocketId> + '_ {
+        self.input_targets[input]
+            .iter()
+            .copied()
+            .filter(move |&x| self.tree.is_valid_socket(x))
+    }
+
+    /// Inner socket that provides the value of the group output, or `None`
+    /// if its node was removed after collapsing.
+    pub fn output_source(&self, output: usize) -> Option<OutputSocketId> {
+        self.output_sources[output].filter(|&x| self.tree.is_valid_socket(x))
+    }
+
+    /// Name of the group input, taken from the inner socket receiving it.
+    pub fn input_name(&self, input: usize) -> String {
+        self.input_targets(input)
+            .next()
+            .and_then(|x| self.tree.socket_name(x))
+            .unwrap_or_else(|| format!("input {}", input + 1))
+    }
+
+    /// Name of the group output, taken from the inner socket providing it.
+    pub fn output_name(&self, output: usize) -> String {
+        self.output_source(output)
+            .and_then(|x| self.tree.socket_name(x))
+            .unwrap_or_else(|| format!("output {}", output + 1))
+    }
+}
+
+impl Tree {
+    /// Group stored in the node, or `None` if it is not a group node.
+    pub fn group(&self, node: NodeId) -> Option<&Group> {
+        match &self.node(node)?.kind {
+            NodeKind::Group(group) => Some(group),
+            _ => None,
+        }
+    }
+
+    fn group_mut(&mut self, node: NodeId) -> Option<&mut Group> {
+        match &mut self.node_mut(node)?.kind {
+            NodeKind::Group(group) => Some(group),
+            _ => None,
+        }
+    }
+
+    /// Tree of the group reached by entering groups along the path one by one.
+    ///
+    /// Returns `None` if any of the nodes on the path is not a group anymore.
+    pub fn subtree(&self, path: &[NodeId]) -> Option<&Tree> {
+        path.iter()
+            .try_fold(self, |tree, &node| tree.group(node).map(|x| &x.tree))
+    }
+
+    /// Mutable version of [`Tree::subtree`].
+    pub fn subtree_mut(&mut self, path: &[NodeId]) -> Option<&mut Tree> {
+        path.iter().try_fold(self, |tree, &node| {
+            tree.group_mut(node).map(|x| &mut x.tree)
+        })
+    }
+
+    /// Replaces nodes with a single group node containing them and selects it.
+    ///
+    /// Returns id of the group and collapsed nodes in the order they were removed,
+    /// or `None` if none of the nodes exist.
+    pub fn collapse(&mut self, nodes: &[NodeId]) -> Option<(NodeId, Vec<RemovedNode>)> {
+        let nodes = nodes
+            .iter()
+            .copied()
+            .filter(|&x| self.node(x).is_some())
+            .collect::<Vec<_>>();
+        if nodes.is_empty() {
+            return None;
+        }
+
+        // Every outer output feeding collapsed nodes becomes one group input,
+        // every collapsed output feeding outer nodes becomes one group output.
+        let mut incoming: Vec<(OutputSocketId, Vec<InputSocketId>)> = Vec::new();
+        let mut outgoing: Vec<(OutputSocketId, Vec<InputSocketId>)> = Vec::new();
+        for connection in &self.connections {
+            let input_inside = nodes.contains(&connection.input.node());
+            let output_inside = nodes.contains(&connection.output.node());
+            let boundary = match (output_inside, input_inside) {
+                (false, true) => &mut incoming,
+                (true, false) => &mut outgoing,
+                _ => continue,
+            };
+            match boundary.iter_mut().find(|(x, _)| *x == connection.output) {
+                Some((_, inputs)) => inputs.push(connection.input),
+                None => boundary.push((connection.output, vec![connection.input])),
+            }
+        }
+
+        let position = nodes
+            .iter()
+            .map(|&x| self.node(x).unwrap().position.to_vector())
+            .fold(Point::default(), |sum, x| sum + x / nodes.len() as f64);
+
+        let removed = nodes
+            .iter()
+            .filter_map(|&x| self.remove_node(x))
+            .collect::<Vec<_>>();
+
+        let mut tree = Tree::new();
+        tree.transform = self.transform;
+        tree.routing = self.routing;
+        let ids = removed
+            .iter()
+            .map(|x| {
+                let mut node = x.node.clone();
+                node.sockets.iter_mut().for_each(|x| x.enabled = false);
+                (x.id, tree.insert_node(node))
+            })
+            .collect::<HashMap<_, _>>();
+        let inner_input = |x: &InputSocketId| (ids[&x.node()], x.id(), x.kind()).into_input();
+        let inner_output = |x: &OutputSocketId| (ids[&x.node()], x.id(), x.kind()).into_output();
+
+        for (input, output) in removed.iter().flat_map(|x| &x.connections) {
+            if ids.contains_key(&input.node()) && ids.contains_key(&output.node()) {
+                tree.connect_sockets(inner_input(input), inner_output(output));
+            }
+        }
+
+        let group = Group {
+            name: "group".to_string(),
+            inputs: incoming
+                .iter()
+                .map(|(_, inputs)| tree.socket_type(inner_input(&inputs[0])))
+                .collect(),
+            outputs: outgoing
+                .iter()
+                .map(|(output, _)| tree.socket_type(inner_output(output)))
+                .collect(),
+            input_targets: incoming
+                .iter()
+                .map(|(_, inputs)| inputs.iter().map(inner_input).collect())
+                .collect(),
+            output_sources: outgoing
+                .iter()
+                .map(|(output, _)| Some(inner_output(output)))
+                .collect(),
+            tree,
+        };
+
+        let mut node = NodeData::new(group);
+        node.position = position;
+        let group = self.insert_node(node);
+
+        for (i, (output, _)) in incoming.iter().enumerate() {
+            self.connect_sockets((group, i, SocketKind::Input).into_input(), *output);
+        }
+        for (i, (_, inputs)) in outgoing.iter().enumerate() {
+            let output = (group, incoming.len() + i, SocketKind::Output).into_output();
+            for &input in inputs {
+                self.connect_sockets(input, output);
+            }
+        }
+
+        self.selection = vec![group];
+        Some((group, removed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::tests::function;
+
+    use super::*;
+
+    #[test]
+    fn collapse() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let first = tree.create_node(function("boolean"), (0.0, 100.0).into());
+        let second = tree.create_node(function("boolean"), (0.0, 200.0).into());
+        let output = tree.create_node(function("output_geo"), (0.0, 300.0).into());
+        let geometry = (input, 0, SocketKind::Output);
+        tree.create_connection(geometry, (first, 0, SocketKind::Input))
+            .unwrap();
+        tree.create_connection(geometry, (first, 1, SocketKind::Input))
+            .unwrap();
+        tree.create_connection(
+            (first, 2, SocketKind::Output),
+            (second, 0, SocketKind::Input),
+        )
+        .unwrap();
+        tree.create_connection(
+            (second, 2, SocketKind::Output),
+            (output, 0, SocketKind::Input),
+        )
+        .unwrap();
+
+        let (group_id, removed) = tree.collapse(&[first, second]).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(tree.nodes().count(), 3);
+        // Input feeds the group once, group feeds the output.
+        assert_eq!(tree.connections.len(), 2);
+
+        let group = tree.group(group_id).unwrap();
+        assert_eq!(group.inputs, [ParamType::f64]);
+        assert_eq!(group.outputs, [ParamType::f64]);
+        assert_eq!(group.input_targets(0).count(), 2);
+        assert_eq!(group.tree().nodes().count(), 2);
+        assert_eq!(group.tree().connections.len(), 1);
+        assert_eq!(tree.node(group_id).unwrap().position, (0.0, 150.0).into());
+
+        let inner = group.tree().nodes().next().unwrap().0;
+        assert!(tree.subtree(&[group_id]).unwrap().node(inner).is_some());
+        assert!(tree.subtree(&[group_id, inner]).is_none());
+    }
+}
@@ -0,0 +1,604 @@
+//! Undo/redo support for the [`Tree`].
+//!
+//! Every change user makes to the tree is described by an [`Edit`].
+//! Applying an edit returns another edit that reverts it, so history
+//! is just two stacks of such inverse edits.
+
+use crate::{
+    function::FunctionDefinition,
+    math::{Point, Rect, Vec2},
+    params::Param,
+};
+
+use super::{InputSocketId, NodeId, OutputSocketId, RemovedNode, Tree};
+
+pub enum Edit {
+    /// Create node at the screen position.
+    CreateNode {
+        function: FunctionDefinition,
+        position: Point,
+    },
+    /// Put back previously removed node along with its connections.
+    RestoreNode(Box<RemovedNode>),
+    /// Remove node along with its connections.
+    RemoveNode(NodeId),
+    CreateConnection {
+        input: InputSocketId,
+        output: OutputSocketId,
+    },
+    DeleteConnection(InputSocketId),
+    /// Create frame covering the rect in screen space.
+    CreateFrame {
+        title: String,
+        rect: Rect,
+    },
+    /// Create note with the top left corner at the screen position.
+    CreateNote {
+        text: String,
+        position: Point,
+    },
+    /// Replace frame title or note text.
+    SetText {
+        node: NodeId,
+        text: String,
+    },
+    /// Set the value the unconnected input gets, `None` brings back the
+    /// default.
+    SetInputValue {
+        input: InputSocketId,
+        value: Option<Param>,
+    },
+    /// Split the connection going to the input with a reroute knot at the
+    /// screen position.
+    Reroute {
+        input: InputSocketId,
+        position: Point,
+    },
+    /// Move node by offset in canvas space.
+    MoveNode {
+        node: NodeId,
+        offset: Vec2,
+    },
+    /// Move several nodes by the same offset in canvas space.
+    MoveNodes {
+        nodes: Vec<NodeId>,
+        offset: Vec2,
+    },
+    /// Move nodes to the positions in canvas space.
+    Arrange(Vec<(NodeId, Point)>),
+    /// Copy nodes along with connections between them, moving copies by offset
+    /// in canvas space. Copies become selected.
+    Duplicate {
+        nodes: Vec<NodeId>,
+        offset: Vec2,
+    },
+    /// Insert copies of all the fragment nodes around the screen position.
+    /// Copies become selected.
+    Paste {
+        fragment: Box<Tree>,
+        position: Point,
+    },
+    /// Replace nodes with a group node containing them.
+    Collapse(Vec<NodeId>),
+    /// Apply edit to the tree of the group node.
+    InGroup {
+        group: NodeId,
+        edit: Box<Edit>,
+    },
+    /// Several edits applied in order as a single one.
+    Batch(Vec<Edit>),
+}
+
+impl Edit {
+    /// Whether edit only moves nodes around, so values they compute stay
+    /// the same.
+    pub fn moves_only(&self) -> bool {
+        match self {
+            Edit::MoveNode { .. } | Edit::MoveNodes { .. } | Edit::Arrange(_) => true,
+            Edit::InGroup { edit, .. } => edit.moves_only(),
+            Edit::Batch(edits) => edits.iter().all(Edit::moves_only),
+            _ => false,
+        }
+    }
+
+    /// Whether edit changes nothing, edits that fail to apply are reverted by
+    /// such ones.
+    fn is_empty(&self) -> bool {
+        match self {
+            Edit::Batch(edits) => edits.iter().all(Edit::is_empty),
+            _ => false,
+        }
+    }
+
+    /// Applies edit to the tree and returns the edit that reverts it.
+    fn apply(self, tree: &mut Tree) -> Edit {
+        match self {
+            Edit::CreateNode { function, position } => {
+                Edit::RemoveNode(tree.create_node(function, position))
+            }
+            Edit::RestoreNode(removed) => {
+                let RemovedNode {
+                    id,
+                    node,
+                    connections,
+                } = *removed;
+                if !tree.restore_node(id, node) {
+                    return Edit::Batch(vec![]);
+                }
+                for (input, output) in connections {
+                    tree.connect_sockets(input, output);
+                }
+                Edit::RemoveNode(id)
+            }
+            Edit::RemoveNode(node) => match tree.remove_node(node) {
+                Some(removed) => Edit::RestoreNode(Box::new(removed)),
+                None => Edit::Batch(vec![]),
+            },
+            Edit::CreateConnection { input, output } => {
+                let replaced = tree.connected_output(input);
+                if replaced == Some(output) {
+                    return Edit::Batch(vec![]);
+                }
+
+                let mut inverse = match tree.create_connection(input, output) {
+                    Ok(None) => vec![Edit::DeleteConnection(input)],
+                    // Removing conversion node removes both of its connections.
+                    Ok(Some(conversion)) => vec![Edit::RemoveNode(conversion)],
+                    Err(_) => return Edit::Batch(vec![]),
+                };
+                if let Some(replaced) = replaced {
+                    inverse.push(Edit::CreateConnection {
+                        input,
+                        output: replaced,
+                    });
+                }
+                Edit::Batch(inverse)
+            }
+            Edit::DeleteConnection(input) => match tree.remove_connection(input) {
+                Some(connection) => Edit::CreateConnection {
+                    input,
+                    output: connection.output,
+                },
+                None => Edit::Batch(vec![]),
+            },
+            Edit::CreateFrame { title, rect } => Edit::RemoveNode(tree.create_frame(title, rect)),
+            Edit::CreateNote { text, position } => {
+                Edit::RemoveNode(tree.create_note(text, position))
+            }
+            Edit::SetText { node, text } => match tree.set_text(node, text) {
+                Some(text) => Edit::SetText { node, text },
+                None => Edit::Batch(vec![]),
+            },
+            Edit::SetInputValue { input, value } => match tree.set_input_value(input, value) {
+                Some(value) => Edit::SetInputValue { input, value },
+                None => Edit::Batch(vec![]),
+            },
+            Edit::Reroute { input, position } => {
+                let output = tree.connected_output(input);
+                match (tree.insert_reroute(input, position), output) {
+                    // Removing the knot removes both of its connections.
+                    (Some(knot), Some(output)) => Edit::Batch(vec![
+                        Edit::RemoveNode(knot),
+                        Edit::CreateConnection { input, output },
+                    ]),
+                    _ => Edit::Batch(vec![]),
+                }
+            }
+            Edit::MoveNode { node, offset } => {
+                tree.move_node(node, offset);
+                Edit::MoveNode {
+                    node,
+                    offset: -offset,
+                }
+            }
+            Edit::MoveNodes { nodes, offset } => {
+                for &node in &nodes {
+                    tree.move_node(node, offset);
+                }
+                Edit::MoveNodes {
+                    nodes,
+                    offset: -offset,
+                }
+            }
+            Edit::Arrange(positions) => Edit::Arrange(
+                positions
+                    .into_iter()
+                    .filter_map(|(node, position)| {
+                        let old = tree.node(node)?.position;
+                        tree.move_node(node, position - old);
+                        Some((node, old))
+                    })
+                    .collect(),
+            ),
+            Edit::Duplicate { nodes, offset } => Edit::Batch(
+                tree.duplicate_nodes(&nodes, offset)
+                    .into_iter()
+                    .map(Edit::RemoveNode)
+                    .collect(),
+            ),
+            Edit::Paste { fragment, position } => Edit::Batch(
+                tree.paste(&fragment, position)
+                    .into_iter()
+                    .map(Edit::RemoveNode)
+                    .collect(),
+            ),
+            Edit::Collapse(nodes) => match tree.collapse(&nodes) {
+                Some((group, removed)) => {
+                    // Collapsed nodes are put back under their old ids, so older
+                    // edits referring to them stay valid.
+                    let restore = removed
+                        .into_iter()
+                        .rev()
+                        .map(|x| Edit::RestoreNode(Box::new(x)));
+                    Edit::Batch(
+                        std::iter::once(Edit::RemoveNode(group))
+                            .chain(restore)
+                            .collect(),
+                    )
+                }
+                None => Edit::Batch(vec![]),
+            },
+            Edit::InGroup { group, edit } => match tree.subtree_mut(&[group]) {
+                Some(subtree) => Edit::InGroup {
+                    group,
+                    edit: Box::new(edit.apply(subtree)),
+                },
+                None => Edit::Batch(vec![]),
+            },
+            Edit::Batch(edits) => {
+                let mut inverse = edits
+                    .into_iter()
+                    .map(|edit| edit.apply(tree))
+                    .collect::<Vec<_>>();
+                inverse.reverse();
+                Edit::Batch(inverse)
+            }
+        }
+    }
+
+    /// Tries to fold `other` into `self`, so both are reverted at once.
+    ///
+    /// Used to keep history small during gestures producing lots of tiny edits.
+    fn merge(&mut self, other: &Edit) -> bool {
+        match (self, other) {
+            (
+                Edit::MoveNode { node, offset },
+                Edit::MoveNode {
+                    node: other_node,
+                    offset: other_offset,
+                },
+            ) if node == other_node => {
+                *offset = *offset + *other_offset;
+                true
+            }
+            (
+                Edit::MoveNodes { nodes, offset },
+                Edit::MoveNodes {
+                    nodes: other_nodes,
+                    offset: other_offset,
+                },
+            ) if nodes == other_nodes => {
+                *offset = *offset + *other_offset;
+                true
+            }
+            (
+                Edit::InGroup { group, edit },
+                Edit::InGroup {
+                    group: other_group,
+                    edit: other_edit,
+                },
+            ) if group == other_group => edit.merge(other_edit),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    /// Length of the undo stack at the moment current gesture started.
+    gesture_start: Option<usize>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    /// Applies edit to the tree and remembers how to revert it.
+    pub fn execute(&mut self, tree: &mut Tree, edit: Edit) {
+        let inverse = edit.apply(tree);
+        self.redo.clear();
+
+        if let Some(start) = self.gesture_start {
+            if self.undo.len() > start {
+                if let Some(last) = self.undo.last_mut() {
+                    if last.merge(&inverse) {
+                        return;
+                    }
+                }
+            }
+        }
+        self.undo.push(inverse);
+    }
+
+    /// All the edits executed until [`History::end_gesture`] will be reverted at once.
+    pub fn begin_gesture(&mut self) {
+        self.end_gesture();
+        self.gesture_start = Some(self.undo.len());
+    }
+
+    pub fn end_gesture(&mut self) {
+        if let Some(start) = self.gesture_start.take() {
+            if self.undo.len() > start + 1 {
+                let mut edits = self.undo.split_off(start);
+                edits.reverse();
+                self.undo.push(Edit::Batch(edits));
+            }
+        }
+    }
+
+    /// Reverts the last edit. Returns `false` if there is nothing to undo or
+    /// the edit can't be reverted anymore, such edit is dropped.
+    pub fn undo(&mut self, tree: &mut Tree) -> bool {
+        self.end_gesture();
+        match self.undo.pop() {
+            Some(edit) => Self::apply(edit, tree, &mut self.redo),
+            None => false,
+        }
+    }
+
+    /// Reapplies the last reverted edit. Returns `false` if there is nothing
+    /// to redo or the edit can't be reapplied anymore, such edit is dropped.
+    pub fn redo(&mut self, tree: &mut Tree) -> bool {
+        self.end_gesture();
+        match self.redo.pop() {
+            Some(edit) => Self::apply(edit, tree, &mut self.undo),
+            None => false,
+        }
+    }
+
+    /// Applies edit popped from one stack and pushes its inverse onto the
+    /// `other` one, unless the edit failed.
+    fn apply(edit: Edit, tree: &mut Tree, other: &mut Vec<Edit>) -> bool {
+        let changes = !edit.is_empty();
+        let inverse = edit.apply(tree);
+        if changes && inverse.is_empty() {
+            return false;
+        }
+        other.push(inverse);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::{tests::function, AsSocketId, ConnectionError, SocketKind};
+
+    fn create(tree: &mut Tree, history: &mut History, name: &str) -> NodeId {
+        history.execute(
+            tree,
+            Edit::CreateNode {
+                function: function(name),
+                position: (0.0, 0.0).into(),
+            },
+        );
+        tree.nodes().last().unwrap().0
+    }
+
+    fn connect(tree: &mut Tree, history: &mut History, from: NodeId, to: NodeId, input: usize) {
+        let output = tree
+            .node(from)
+            .unwrap()
+            .socket_id(SocketKind::Output, 0)
+            .unwrap();
+        history.execute(
+            tree,
+            Edit::CreateConnection {
+                input: (to, input, SocketKind::Input).into_input(),
+                output: (from, output, SocketKind::Output).into_output(),
+            },
+        );
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut tree = Tree::new();
+        let mut history = History::new();
+        let input = create(&mut tree, &mut history, "input_geo");
+        let boolean = create(&mut tree, &mut history, "boolean");
+        connect(&mut tree, &mut history, input, boolean, 0);
+        assert_eq!(tree.connections.len(), 1);
+
+        assert!(history.undo(&mut tree));
+        assert_eq!(tree.connections.len(), 0);
+        assert!(history.undo(&mut tree));
+        assert!(tree.node(boolean).is_none());
+
+        assert!(history.redo(&mut tree));
+        assert!(history.redo(&mut tree));
+        assert!(tree.node(boolean).is_some());
+        assert_eq!(tree.connections.len(), 1);
+        assert!(!history.redo(&mut tree));
+    }
+
+    #[test]
+    fn undo_node_removal() {
+        let mut tree = Tree::new();
+        let mut history = History::new();
+        let input = create(&mut tree, &mut history, "input_geo");
+        let boolean = create(&mut tree, &mut history, "boolean");
+        let output = create(&mut tree, &mut history, "output_geo");
+        connect(&mut tree, &mut history, input, boolean, 0);
+        connect(&mut tree, &mut history, boolean, output, 0);
+
+        history.execute(&mut tree, Edit::RemoveNode(boolean));
+        assert!(tree.node(boolean).is_none());
+        assert!(tree.connections.is_empty());
+        assert!(tree
+            .nodes()
+            .all(|(_, node)| node.sockets.iter().all(|x| !x.enabled)));
+
+        assert!(history.undo(&mut tree));
+        assert!(tree.node(boolean).is_some());
+        assert_eq!(tree.connections.len(), 2);
+        assert!(tree.node(output).unwrap().sockets[0].enabled);
+
+        // Node created past the history takes the slot, undo leaves it be.
+        history.execute(&mut tree, Edit::RemoveNode(boolean));
+        let other = tree.create_node(function("boolean"), (0.0, 0.0).into());
+        assert!(!history.undo(&mut tree));
+        assert!(tree.node(boolean).is_none());
+        assert!(tree.node(other).is_some());
+        assert!(!history.redo(&mut tree));
+    }
+
+    #[test]
+    fn undo_conversion() {
+        let mut tree = Tree::new();
+        let mut history = History::new();
+        let i64_to_f64 = create(&mut tree, &mut history, "i64_to_f64");
+        let output = create(&mut tree, &mut history, "output_geo");
+        let asd = create(&mut tree, &mut history, "asd");
+
+        // `asd` accepts `i64`, but the conversion gives `f64`.
+        let input = (asd, 0, SocketKind::Input);
+        let f64_output = (i64_to_f64, 1, SocketKind::Output);
+        assert!(matches!(
+            tree.can_connect(input, f64_output),
+            Err(ConnectionError::TypeMismatch { .. })
+        ));
+
+        // `output_geo` accepts `f64`, and `asd` gives `f32`.
+        let input = (output, 0, SocketKind::Input);
+        let f32_output = (asd, 2, SocketKind::Output);
+        assert_eq!(
+            tree.can_connect(input, f32_output)
+                .unwrap()
+                .conversion
+                .unwrap()
+                .name,
+            "std.f32_to_f64"
+        );
+        connect(&mut tree, &mut history, asd, output, 0);
+        assert_eq!(tree.nodes().count(), 4);
+        assert_eq!(tree.connections.len(), 2);
+
+        assert!(history.undo(&mut tree));
+        assert_eq!(tree.nodes().count(), 3);
+        assert!(tree.connections.is_empty());
+
+        assert!(history.redo(&mut tree));
+        assert_eq!(tree.nodes().count(), 4);
+        assert_eq!(tree.connections.len(), 2);
+    }
+
+    #[test]
+    fn gesture_is_undone_at_once() {
+        let mut tree = Tree::new();
+        let mut history = History::new();
+        let node = create(&mut tree, &mut history, "input_geo");
+
+        history.begin_gesture();
+        for _ in 0..10 {
+            history.execute(
+                &mut tree,
+                Edit::MoveNode {
+                    node,
+                    offset: [1.0, 2.0].into(),
+                },
+            );
+        }
+        history.end_gesture();
+        assert_eq!(tree.node(node).unwrap().position, (10.0, 20.0).into());
+
+        assert!(history.undo(&mut tree));
+        assert_eq!(tree.node(node).unwrap().position, (0.0, 0.0).into());
+    }
+
+    #[test]
+    fn selection_edits() {
+        let mut tree = Tree::new();
+        let mut history = History::new();
+        let input = create(&mut tree, &mut history, "input_geo");
+        let output = create(&mut tree, &mut history, "output_geo");
+        connect(&mut tree, &mut history, input, output, 0);
+        let nodes = vec![input, output];
+
+        history.execute(
+            &mut tree,
+            Edit::Duplicate {
+                nodes: nodes.clone(),
+                offset: [0.0, 300.0].into(),
+            },
+        );
+        assert_eq!(tree.nodes().count(), 4);
+        assert_eq!(tree.connections.len(), 2);
+
+        let copies = tree.selection().to_vec();
+        let removal = copies.iter().map(|&x| Edit::RemoveNode(x)).collect();
+        history.execute(&mut tree, Edit::Batch(removal));
+        assert_eq!(tree.nodes().count(), 2);
+        assert!(history.undo(&mut tree));
+        assert_eq!(tree.connections.len(), 2);
+        assert!(history.undo(&mut tree));
+        assert_eq!(tree.nodes().count(), 2);
+
+        history.begin_gesture();
+        for _ in 0..5 {
+            history.execute(
+                &mut tree,
+                Edit::MoveNodes {
+                    nodes: nodes.clone(),
+                    offset: [2.0, 0.0].into(),
+                },
+            );
+        }
+        history.end_gesture();
+        assert_eq!(tree.node(output).unwrap().position, (10.0, 0.0).into());
+        assert!(history.undo(&mut tree));
+        assert_eq!(tree.node(input).unwrap().position, (0.0, 0.0).into());
+    }
+
+    #[test]
+    fn undo_collapse() {
+        let mut tree = Tree::new();
+        let mut history = History::new();
+        let input = create(&mut tree, &mut history, "input_geo");
+        let boolean = create(&mut tree, &mut history, "boolean");
+        let output = create(&mut tree, &mut history, "output_geo");
+        connect(&mut tree, &mut history, input, boolean, 0);
+        connect(&mut tree, &mut history, boolean, output, 0);
+
+        history.execute(&mut tree, Edit::Collapse(vec![input, boolean]));
+        let group = tree.selection()[0];
+        assert!(tree.node(input).is_none());
+        assert_eq!(tree.connections.len(), 1);
+
+        let inner = tree.group(group).unwrap().tree().nodes().next().unwrap().0;
+        history.execute(
+            &mut tree,
+            Edit::InGroup {
+                group,
+                edit: Box::new(Edit::RemoveNode(inner)),
+            },
+        );
+        assert_eq!(tree.group(group).unwrap().tree().nodes().count(), 1);
+
+        assert!(history.undo(&mut tree));
+        assert_eq!(tree.group(group).unwrap().tree().nodes().count(), 2);
+        assert!(history.undo(&mut tree));
+        assert!(tree.node(group).is_none());
+        assert!(tree.node(input).is_some() && tree.node(boolean).is_some());
+        assert_eq!(tree.connections.len(), 2);
+
+        // Edits made before collapsing still refer to the right nodes.
+        assert!(history.undo(&mut tree));
+        assert_eq!(tree.connections.len(), 1);
+        assert!(history.redo(&mut tree));
+        assert!(history.redo(&mut tree));
+        assert_eq!(tree.node(group).unwrap().kind().name(), "group");
+    }
+}
@@ -0,0 +1,416 @@
+//! Layered ("tidy up") layout of the tree.
+//!
+//! Follows the Sugiyama approach: nodes are split into layers so every
+//! connection goes right from an output to an input, long connections get
+//! dummy vertices in the layers they pass, vertices inside of the layers are
+//! reordered to reduce crossings and finally get their coordinates.
+
+use std::collections::HashMap;
+
+use crate::math::{Point, Size, Vec2};
+
+use super::{AsSocketId, NodeId, Tree};
+
+/// Horizontal gap between the widest nodes of adjacent layers.
+const LAYER_GAP: f64 = 100.0;
+/// Vertical gap between the nodes of the same layer.
+const NODE_GAP: f64 = 40.0;
+/// Height reserved for connections passing the layer.
+const DUMMY_HEIGHT: f64 = 40.0;
+const ORDERING_SWEEPS: usize = 8;
+const PLACEMENT_SWEEPS: usize = 8;
+
+/// Real node or a point long connection passes through.
+struct Vertex {
+    node: Option<NodeId>,
+    size: Size,
+    layer: usize,
+    /// Position inside of the layer.
+    y: f64,
+    /// Vertices in the previous layer connected to this one.
+    previous: Vec<usize>,
+    /// Vertices in the next layer connected to this one.
+    next: Vec<usize>,
+}
+
+impl Tree {
+    /// New positions of the nodes in canvas space, laid out in layers so that
+    /// connections go from left to right with as few crossings as possible.
+    ///
+    /// Only connections between the nodes are considered, the rest of the
+    /// tree and annotations are left alone. Laid out nodes keep their center
+    /// of mass.
+    pub fn layout(&self, nodes: &[NodeId]) -> Vec<(NodeId, Point)> {
+        let mut nodes = nodes
+            .iter()
+            .copied()
+            .filter(|&x| self.node(x).is_some_and(|x| !x.is_annotation()))
+            .collect::<Vec<_>>();
+        nodes.sort_unstable_by_key(|x| x.index);
+        nodes.dedup();
+        if nodes.is_empty() {
+            return Vec::new();
+        }
+
+        let mut vertices = self.layered_vertices(&nodes);
+        let mut layers = vec![Vec::new(); vertices.iter().map(|x| x.layer + 1).max().unwrap()];
+        for (i, vertex) in vertices.iter().enumerate() {
+            layers[vertex.layer].push(i);
+        }
+        order_layers(&vertices, &mut layers);
+        place_layers(&mut vertices, &layers);
+
+        let layer_x = layer_positions(&vertices, &layers);
+        let positions = vertices
+            .iter()
+            .filter_map(|x| Some((x.node?, Point::new(layer_x[x.layer], x.y))))
+            .collect::<Vec<_>>();
+        let offset = center_of_mass(nodes.iter().map(|&x| self.node(x).unwrap().position))
+            - center_of_mass(positions.iter().map(|&(_, x)| x));
+
+        positions
+            .into_iter()
+            .map(|(node, position)| (node, position + offset))
+            .collect()
+    }
+
+    /// Assigns nodes to layers by the longest path from the sources and splits
+    /// connections spanning several layers with dummy vertices.
+    ///
+    /// Initial `y` of the vertices is taken from the current node positions,
+    /// so the layout keeps the order user had where it can.
+    fn layered_vertices(&self, nodes: &[NodeId]) -> Vec<Vertex> {
+        let ids = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| (x, i))
+            .collect::<HashMap<_, _>>();
+        let mut edges = self
+            .connections
+            .iter()
+            .filter_map(|x| Some((*ids.get(&x.output.node())?, *ids.get(&x.input.node())?)))
+            .collect::<Vec<_>>();
+        edges.sort_unstable();
+        edges.dedup();
+
+        // Kahn's algorithm, the tree has no cycles.
+        let mut incoming = vec![0; nodes.len()];
+        edges.iter().for_each(|&(_, to)| incoming[to] += 1);
+        let mut layer = vec![0; nodes.len()];
+        let mut ready = (0..nodes.len())
+            .filter(|&x| incoming[x] == 0)
+            .collect::<Vec<_>>();
+        while let Some(node) = ready.pop() {
+            for &(_, to) in edges.iter().filter(|&&(from, _)| from == node) {
+                layer[to] = layer[to].max(layer[node] + 1);
+                incoming[to] -= 1;
+                if incoming[to] == 0 {
+                    ready.push(to);
+                }
+            }
+        }
+
+        let mut vertices = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, &node)| {
+                let node_rect = self.node(node).unwrap().bound_rect();
+                Vertex {
+                    node: Some(node),
+                    size: node_rect.size(),
+                    layer: layer[i],
+                    y: node_rect.center().y,
+                    previous: Vec::new(),
+                    next: Vec::new(),
+                }
+            })
+            .collect::<Vec<_>>();
+        for (from, to) in edges {
+            if layer[to] <= layer[from] {
+                continue;
+            }
+            let (start, end) = (vertices[from].y, vertices[to].y);
+            let span = layer[to] - layer[from];
+            let mut previous = from;
+            for step in 1..span {
+                vertices.push(Vertex {
+                    node: None,
+                    size: Size {
+                        width: 0.0,
+                        height: DUMMY_HEIGHT,
+                    },
+                    layer: layer[from] + step,
+                    y: start + (end - start) * step as f64 / span as f64,
+                    previous: vec![previous],
+                    next: Vec::new(),
+                });
+                let dummy = vertices.len() - 1;
+                vertices[previous].next.push(dummy);
+                previous = dummy;
+            }
+            vertices[previous].next.push(to);
+            vertices[to].previous.push(previous);
+        }
+        vertices
+    }
+}
+
+/// `x` of the layer centers, so the widest nodes of adjacent layers are
+/// `LAYER_GAP` apart.
+fn layer_positions(vertices: &[Vertex], layers: &[Vec<usize>]) -> Vec<f64> {
+    let widths = layers
+        .iter()
+        .map(|layer| {
+            layer
+                .iter()
+                .map(|&x| vertices[x].size.width)
+                .fold(0.0, f64::max)
+        })
+        .collect::<Vec<_>>();
+    let mut x = 0.0;
+    (0..layers.len())
+        .map(|i| {
+            if i > 0 {
+                x += (widths[i - 1] + widths[i]) / 2.0 + LAYER_GAP;
+            }
+            x
+        })
+        .collect()
+}
+
+fn center_of_mass(points: impl Iterator<Item = Point>) -> Vec2 {
+    let (sum, count) = points.fold((Vec2::ZERO, 0), |(sum, count), x| {
+        (sum + x.to_vector(), count + 1)
+    });
+    sum / count as f64
+}
+
+/// Reorders vertices inside of the layers by the barycenter of their
+/// neighbours, sweeping forward and back and keeping the order with the
+/// fewest crossings.
+fn order_layers(vertices: &[Vertex], layers: &mut [Vec<usize>]) {
+    for layer in layers.iter_mut() {
+        layer.sort_by(|&a, &b| vertices[a].y.partial_cmp(&vertices[b].y).unwrap());
+    }
+
+    let mut best = layers.to_vec();
+    let mut best_crossings = crossings(vertices, layers);
+    for sweep in 0..ORDERING_SWEEPS {
+        let mut rank = vec![0.0; vertices.len()];
+        let update_rank = |rank: &mut Vec<f64>, layer: &[usize]| {
+            layer
+                .iter()
+                .enumerate()
+                .for_each(|(i, &x)| rank[x] = i as f64);
+        };
+        layers.iter().for_each(|x| update_rank(&mut rank, x));
+
+        let forward = sweep % 2 == 0;
+        let order = if forward {
+            (1..layers.len()).collect::<Vec<_>>()
+        } else {
+            (0..layers.len() - 1).rev().collect()
+        };
+        for i in order {
+            let barycenter = |&x: &usize| {
+                let neighbours = if forward {
+                    &vertices[x].previous
+                } else {
+                    &vertices[x].next
+                };
+                if neighbours.is_empty() {
+                    // Stays where it is.
+                    rank[x]
+                } else {
+                    neighbours.iter().map(|&x| rank[x]).sum::<f64>() / neighbours.len() as f64
+                }
+            };
+            let mut keyed = layers[i]
+                .iter()
+                .map(|x| (barycenter(x), *x))
+                .collect::<Vec<_>>();
+            // Stable sort keeps the current order of ties.
+            keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            layers[i] = keyed.into_iter().map(|(_, x)| x).collect();
+            update_rank(&mut rank, &layers[i]);
+        }
+
+        let count = crossings(vertices, layers);
+        if count < best_crossings {
+            best_crossings = count;
+            best = layers.to_vec();
+        }
+    }
+    layers.clone_from_slice(&best);
+}
+
+/// Number of crossing connections between all adjacent layers.
+fn crossings(vertices: &[Vertex], layers: &[Vec<usize>]) -> usize {
+    let mut rank = vec![0; vertices.len()];
+    for layer in layers {
+        layer.iter().enumerate().for_each(|(i, &x)| rank[x] = i);
+    }
+    let rank = &rank;
+
+    layers
+        .iter()
+        .map(|layer| {
+            let edges = layer
+                .iter()
+                .flat_map(|&x| vertices[x].next.iter().map(move |&y| (rank[x], rank[y])))
+                .collect::<Vec<_>>();
+            let mut count = 0;
+            for (i, a) in edges.iter().enumerate() {
+                for b in &edges[i + 1..] {
+                    if (a.0 < b.0 && a.1 > b.1) || (a.0 > b.0 && a.1 < b.1) {
+                        count += 1;
+                    }
+                }
+            }
+            count
+        })
+        .sum()
+}
+
+/// Sets `y` of the vertices, pulling them towards their neighbours while
+/// keeping the order inside of the layers and the gaps between vertices.
+fn place_layers(vertices: &mut [Vertex], layers: &[Vec<usize>]) {
+    for layer in layers {
+        let desired = vec![0.0; layer.len()];
+        pack(vertices, layer, &desired);
+    }
+
+    for sweep in 0..PLACEMENT_SWEEPS {
+        let forward = sweep % 2 == 0;
+        let order = if forward {
+            (1..layers.len()).collect::<Vec<_>>()
+        } else {
+            (0..layers.len() - 1).rev().collect()
+        };
+        for i in order {
+            let desired = layers[i]
+                .iter()
+                .map(|&x| {
+                    let neighbours = if forward {
+                        &vertices[x].previous
+                    } else {
+                        &vertices[x].next
+                    };
+                    if neighbours.is_empty() {
+                        vertices[x].y
+                    } else {
+                        neighbours.iter().map(|&x| vertices[x].y).sum::<f64>()
+                            / neighbours.len() as f64
+                    }
+                })
+                .collect::<Vec<_>>();
+            pack(vertices, &layers[i], &desired);
+        }
+    }
+}
+
+/// Places vertices of the layer as close to the desired `y` as the gaps allow.
+///
+/// Averages the tightest placements from the top and from the bottom, both
+/// keep the gaps, so the average keeps them too.
+fn pack(vertices: &mut [Vertex], layer: &[usize], desired: &[f64]) {
+    let gap = |a: usize, b: usize| {
+        (vertices[layer[a]].size.height + vertices[layer[b]].size.height) / 2.0 + NODE_GAP
+    };
+
+    let mut top = desired.to_vec();
+    for i in 1..layer.len() {
+        top[i] = top[i].max(top[i - 1] + gap(i - 1, i));
+    }
+    let mut bottom = desired.to_vec();
+    for i in (0..layer.len().saturating_sub(1)).rev() {
+        bottom[i] = bottom[i].min(bottom[i + 1] - gap(i, i + 1));
+    }
+
+    for (i, &x) in layer.iter().enumerate() {
+        vertices[x].y = (top[i] + bottom[i]) / 2.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::{tests::function, SocketKind};
+
+    use super::*;
+
+    #[test]
+    fn layered_layout() {
+        let mut tree = Tree::new();
+        // Both inputs feed both booleans crosswise, placed so connections cross.
+        let left = tree.create_node(function("input_geo"), (0.0, 500.0).into());
+        let right = tree.create_node(function("input_geo"), (300.0, 0.0).into());
+        let first = tree.create_node(function("boolean"), (300.0, 300.0).into());
+        let second = tree.create_node(function("boolean"), (0.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (900.0, 900.0).into());
+        for &(from, to, socket) in &[
+            (left, first, 0),
+            (right, second, 0),
+            (left, output, 0),
+            (first, second, 1),
+        ] {
+            let output_socket = if from == first { 2 } else { 0 };
+            tree.create_connection(
+                (from, output_socket, SocketKind::Output),
+                (to, socket, SocketKind::Input),
+            )
+            .unwrap();
+        }
+
+        let nodes = tree.nodes().map(|(id, _)| id).collect::<Vec<_>>();
+        let positions = tree.layout(&nodes).into_iter().collect::<HashMap<_, _>>();
+        assert_eq!(positions.len(), nodes.len());
+
+        // Every connection goes right.
+        for connection in &tree.connections {
+            let from = positions[&connection.output.node()];
+            let to = positions[&connection.input.node()];
+            assert!(to.x > from.x);
+        }
+        assert_eq!(positions[&left].x, positions[&right].x);
+        let size = |x: NodeId| tree.node(x).unwrap().size;
+        assert_eq!(
+            positions[&second].x - positions[&first].x,
+            size(first).width + LAYER_GAP
+        );
+
+        // Nodes of the same layer don't overlap.
+        for (a, pa) in &positions {
+            for (b, pb) in &positions {
+                if a != b && pa.x == pb.x {
+                    let gap = (size(*a).height + size(*b).height) / 2.0;
+                    assert!((pa.y - pb.y).abs() >= gap);
+                }
+            }
+        }
+
+        let before = center_of_mass(nodes.iter().map(|&x| tree.node(x).unwrap().position));
+        let after = center_of_mass(positions.values().copied());
+        assert!((before - after).len() < 1e-6);
+    }
+
+    #[test]
+    fn layout_of_selection() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (0.0, -500.0).into());
+        let other = tree.create_node(function("output_geo"), (1000.0, 0.0).into());
+        tree.create_connection(
+            (input, 0, SocketKind::Output),
+            (output, 0, SocketKind::Input),
+        )
+        .unwrap();
+
+        let positions = tree.layout(&[input, output]);
+        assert_eq!(positions.len(), 2);
+        assert!(positions.iter().all(|&(x, _)| x != other));
+        let width = |x: NodeId| tree.node(x).unwrap().size.width;
+        let spacing = (width(input) + width(output)) / 2.0 + LAYER_GAP;
+        assert_eq!(positions[0].1, (-spacing / 2.0, -250.0).into());
+        assert_eq!(positions[1].1, (spacing / 2.0, -250.0).into());
+    }
+}
@@ -0,0 +1,421 @@
+//! Trees compiled to a linear list of instructions working on registers.
+//!
+//! Groups are inlined and reroute knots disappear, so program is just the
+//! function calls in topological order, with the values of the unconnected
+//! inputs written to the registers right before they are read. Register of
+//! the value is reused once the last instruction reading it is done, so
+//! programs need as many registers as there are values alive at the same
+//! time, not as there are sockets.
+//!
+//! Programs are saved as JSON and run without the tree they were compiled from.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    function::{FunctionDefinition, FunctionError},
+    params::{Param, ParamType},
+};
+
+use super::{evaluation::Call, Diagnostic, Severity, Tree};
+
+/// Version written to every saved program, see [`super::document::DOCUMENT_VERSION`].
+///
+/// - 1: initial version.
+/// - 2: functions are referred to by namespaced names.
+/// - 3: constants.
+pub const PROGRAM_VERSION: u32 = 3;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Instruction {
+    /// Calls the function with the values of the `inputs` registers and
+    /// writes its results to the `outputs` registers.
+    Call {
+        function: String,
+        inputs: Vec<usize>,
+        outputs: Vec<usize>,
+    },
+    /// Writes the value of the unconnected input to the `output` register.
+    Constant { value: Param, output: usize },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Program {
+    version: u32,
+    /// Number of the registers instructions use.
+    registers: usize,
+    instructions: Vec<Instruction>,
+    /// Values passed to the nodes without outputs, named after the node, its
+    /// index and its input, like `output_geo#3.geometry`, with the registers
+    /// holding them when the program ends.
+    results: Vec<(String, usize)>,
+}
+
+#[derive(Debug)]
+pub enum ProgramError {
+    /// Text is not a valid program at all.
+    Syntax(serde_json::Error),
+    /// Program was saved by a newer version of the editor.
+    UnsupportedVersion(u32),
+    /// Instruction calls function that is not in the `FunctionRegistry`.
+    UnknownFunction { instruction: usize, name: String },
+    /// Instruction reads register no instruction wrote before, or reads it as
+    /// a value of a wrong type, or passes wrong number of values.
+    InvalidInstruction { instruction: usize },
+    /// Result refers to a register no instruction wrote.
+    InvalidResult { result: usize },
+    /// Function called by the instruction failed while the program was run.
+    Failed {
+        instruction: usize,
+        error: FunctionError,
+    },
+}
+
+impl std::fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ProgramError::Syntax(err) => write!(f, "malformed program: {}", err),
+            ProgramError::UnsupportedVersion(version) => write!(
+                f,
+                "program version {} is newer than supported version {}",
+                version, PROGRAM_VERSION
+            ),
+            ProgramError::UnknownFunction { instruction, name } => write!(
+                f,
+                "instruction #{} calls unknown function `{}`",
+                instruction, name
+            ),
+            ProgramError::InvalidInstruction { instruction } => {
+                write!(f, "instruction #{} uses registers incorrectly", instruction)
+            }
+            ProgramError::InvalidResult { result } => {
+                write!(f, "result #{} is never computed", result)
+            }
+            ProgramError::Failed { instruction, error } => {
+                write!(f, "instruction #{} failed: {}", instruction, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProgramError {}
+
+impl From<serde_json::Error> for ProgramError {
+    fn from(err: serde_json::Error) -> Self {
+        ProgramError::Syntax(err)
+    }
+}
+
+#[derive(Deserialize)]
+struct ProgramHeader {
+    version: u32,
+}
+
+impl Tree {
+    /// Compiles tree to the program computing the same values.
+    ///
+    /// Tree is validated first, errors found by [`Tree::validate`] are
+    /// returned instead.
+    pub fn compile(&self) -> Result<Program, Vec<Diagnostic>> {
+        let errors = self
+            .validate()
+            .into_iter()
+            .filter(|x| x.severity == Severity::Error)
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let lowered = self.lower();
+        // Nodes without outputs are function nodes of the root tree, they
+        // read the results like any other inputs.
+        let results = lowered
+            .calls
+            .iter()
+            .filter(|call| call.node.len() == 1 && call.outputs.is_empty())
+            .flat_map(|call| {
+                let id = call.node[0];
+                let kind = &self.node(id).unwrap().kind;
+                // Index tells apart the nodes of the same function.
+                call.inputs.iter().enumerate().map(move |(index, &value)| {
+                    let name = format!("{}#{}.{}", kind.name(), id.index, kind.input_name(index));
+                    (name, value)
+                })
+            })
+            .collect();
+        Ok(Program::allocate(
+            lowered.calls,
+            lowered.values,
+            lowered.constants,
+            results,
+        ))
+    }
+}
+
+impl Program {
+    /// Assigns registers to the values, reusing registers of the values no
+    /// instruction reads anymore. Constants are written right before the
+    /// first call reading them.
+    fn allocate(
+        calls: Vec<Call>,
+        values: usize,
+        constants: Vec<(usize, Param)>,
+        results: Vec<(String, usize)>,
+    ) -> Program {
+        // Index of the last call reading each value, results are read after
+        // all of them.
+        let mut last_use = vec![None; values];
+        for (index, call) in calls.iter().enumerate() {
+            for &value in &call.inputs {
+                last_use[value] = Some(index);
+            }
+        }
+        for &(_, value) in &results {
+            last_use[value] = Some(calls.len());
+        }
+
+        let mut constants = constants.into_iter().collect::<HashMap<_, _>>();
+        let mut registers = vec![usize::MAX; values];
+        let mut free = Vec::new();
+        let mut count = 0;
+        let mut take = |free: &mut Vec<usize>| {
+            free.pop().unwrap_or_else(|| {
+                count += 1;
+                count - 1
+            })
+        };
+        let mut instructions = Vec::with_capacity(calls.len());
+        for (index, call) in calls.into_iter().enumerate() {
+            for &value in &call.inputs {
+                if let Some(constant) = constants.remove(&value) {
+                    registers[value] = take(&mut free);
+                    instructions.push(Instruction::Constant {
+                        value: constant,
+                        output: registers[value],
+                    });
+                }
+            }
+            // Inputs are read before outputs are written, so outputs can take
+            // registers of the inputs read for the last time.
+            for &value in &call.inputs {
+                if last_use[value] == Some(index) && !free.contains(&registers[value]) {
+                    free.push(registers[value]);
+                }
+            }
+            let inputs = call.inputs.iter().map(|&x| registers[x]).collect();
+            for &value in &call.outputs {
+                registers[value] = take(&mut free);
+            }
+            let outputs = call.outputs.iter().map(|&x| registers[x]).collect();
+            // Values nobody reads are written and forgotten.
+            for &value in &call.outputs {
+                if last_use[value].is_none() {
+                    free.push(registers[value]);
+                }
+            }
+            instructions.push(Instruction::Call {
+                function: call.function.name.to_string(),
+                inputs,
+                outputs,
+            });
+        }
+
+        Program {
+            version: PROGRAM_VERSION,
+            registers: count,
+            instructions,
+            results: results
+                .into_iter()
+                .map(|(name, value)| (name, registers[value]))
+                .collect(),
+        }
+    }
+
+    /// Number of the registers program needs.
+    pub fn registers(&self) -> usize {
+        self.registers
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Runs the program and returns the named results, or the error of the
+    /// first function that failed.
+    pub fn run(&self) -> Result<Vec<(String, Param)>, ProgramError> {
+        let mut registers: Vec<Option<Param>> = vec![None; self.registers];
+        let mut memory = Vec::new();
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            match instruction {
+                Instruction::Call {
+                    function,
+                    inputs,
+                    outputs,
+                } => {
+                    let function =
+                        FunctionDefinition::find(function).expect("functions are checked on load");
+                    memory.clear();
+                    memory.extend(inputs.iter().map(|&x| registers[x].clone().unwrap()));
+                    let addresses = (0..inputs.len()).collect::<Vec<_>>();
+                    function.call(&addresses, &mut memory).map_err(|error| {
+                        ProgramError::Failed {
+                            instruction: index,
+                            error,
+                        }
+                    })?;
+                    for (&register, value) in outputs.iter().zip(memory.drain(inputs.len()..)) {
+                        registers[register] = Some(value);
+                    }
+                }
+                Instruction::Constant { value, output } => {
+                    registers[*output] = Some(value.clone());
+                }
+            }
+        }
+        Ok(self
+            .results
+            .iter()
+            .map(|(name, register)| (name.clone(), registers[*register].clone().unwrap()))
+            .collect())
+    }
+
+    /// Serializes program, so it can be run without the editor.
+    pub fn save(&self) -> String {
+        serde_json::to_string_pretty(self).expect("program is always serializable")
+    }
+
+    /// Restores program saved by [`Program::save`].
+    ///
+    /// Program is checked, so running it never reads a register before it is
+    /// written or passes a value of the wrong type to a function.
+    pub fn load(text: &str) -> Result<Program, ProgramError> {
+        let header: ProgramHeader = serde_json::from_str(text)?;
+        if header.version > PROGRAM_VERSION {
+            return Err(ProgramError::UnsupportedVersion(header.version));
+        }
+
+        let program: Program = serde_json::from_str(text)?;
+        let mut types: Vec<Option<ParamType>> = vec![None; program.registers];
+        for (index, instruction) in program.instructions.iter().enumerate() {
+            let invalid = || ProgramError::InvalidInstruction { instruction: index };
+            match instruction {
+                Instruction::Call {
+                    function,
+                    inputs,
+                    outputs,
+                } => {
+                    let definition = FunctionDefinition::find(function).ok_or_else(|| {
+                        ProgramError::UnknownFunction {
+                            instruction: index,
+                            name: function.clone(),
+                        }
+                    })?;
+                    if inputs.len() != definition.inputs.len()
+                        || outputs.len() != definition.outputs.len()
+                    {
+                        return Err(invalid());
+                    }
+                    for (&register, &ty) in inputs.iter().zip(definition.inputs) {
+                        if types.get(register).copied().flatten() != Some(ty) {
+                            return Err(invalid());
+                        }
+                    }
+                    for (&register, &ty) in outputs.iter().zip(definition.outputs) {
+                        *types.get_mut(register).ok_or_else(invalid)? = Some(ty);
+                    }
+                }
+                Instruction::Constant { value, output } => {
+                    *types.get_mut(*output).ok_or_else(invalid)? = Some(value.get_type());
+                }
+            }
+        }
+        for (index, (_, register)) in program.results.iter().enumerate() {
+            if types.get(*register).copied().flatten().is_none() {
+                return Err(ProgramError::InvalidResult { result: index });
+            }
+        }
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::{
+        tests::{function, socket},
+        AsSocketId, SocketKind,
+    };
+
+    use super::*;
+
+    #[test]
+    fn programs_reuse_registers() {
+        let mut tree = Tree::new();
+        // Chain of booleans, each one needs only the value of the previous one.
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let mut last = input;
+        for i in 0..4 {
+            let boolean = tree.create_node(function("boolean"), (300.0 * i as f64, 0.0).into());
+            for index in 0..2 {
+                let output = tree.node(last).unwrap().kind.inputs().len();
+                tree.create_connection(
+                    socket(last, output, SocketKind::Output),
+                    socket(boolean, index, SocketKind::Input),
+                )
+                .unwrap();
+            }
+            last = boolean;
+        }
+        let output = tree.create_node(function("output_geo"), (1500.0, 0.0).into());
+        tree.create_connection(
+            socket(last, 2, SocketKind::Output),
+            socket(output, 0, SocketKind::Input),
+        )
+        .unwrap();
+        tree.collapse(&[last]);
+
+        let program = tree.compile().unwrap();
+        assert_eq!(program.instructions().len(), 6);
+        assert_eq!(program.registers(), 1);
+        let name = format!("output_geo#{}.geometry", output.index);
+        let results = vec![(name, Param::f64(0.0))];
+        assert_eq!(program.run().unwrap(), results);
+
+        let loaded = Program::load(&program.save()).unwrap();
+        assert_eq!(loaded, program);
+        assert_eq!(loaded.run().unwrap(), results);
+
+        let mut broken = program;
+        broken.instructions.swap(0, 1);
+        assert!(matches!(
+            Program::load(&broken.save()),
+            Err(ProgramError::InvalidInstruction { instruction: 0 })
+        ));
+    }
+
+    #[test]
+    fn results_of_the_same_function_have_different_names() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let outputs = [
+            tree.create_node(function("output_geo"), (300.0, 0.0).into()),
+            tree.create_node(function("output_geo"), (300.0, 300.0).into()),
+        ];
+        for &output in &outputs {
+            tree.create_connection(
+                (input, 0, SocketKind::Output).into_generic(),
+                (output, 0, SocketKind::Input).into_generic(),
+            )
+            .unwrap();
+        }
+
+        let results = tree.compile().unwrap().run().unwrap();
+        let names = results.iter().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(names.len(), 2);
+        assert_ne!(names[0], names[1]);
+        for output in &outputs {
+            let name = format!("output_geo#{}.geometry", output.index);
+            assert!(names.contains(&&name));
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Reroute knots let user bend long connections around other nodes.
+
+use crate::math::Point;
+
+use super::{AsSocketId, InputSocketId, NodeData, NodeId, NodeKind, SocketKind, Tree};
+
+impl Tree {
+    /// Splits the connection going to the `input` with a reroute knot at the
+    /// screen position.
+    ///
+    /// Knot has the type of the connection. Returns `None` if the input is
+    /// not connected.
+    pub fn insert_reroute(&mut self, input: InputSocketId, position: Point) -> Option<NodeId> {
+        let output = self.connected_output(input)?;
+        let mut node = NodeData::new(NodeKind::Reroute(self.socket_type(output)));
+        node.position = self.screen_to_canvas(position);
+        let knot = self.insert_node(node);
+
+        self.connect_sockets((knot, 0, SocketKind::Input).into_input(), output);
+        self.connect_sockets(input, (knot, 1, SocketKind::Output).into_output());
+        Some(knot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        params::ParamType,
+        tree::{tests::function, CastResult},
+    };
+
+    use super::*;
+
+    #[test]
+    fn reroute() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (0.0, 400.0).into());
+        let target = (output, 0, SocketKind::Input).into_input();
+        tree.create_connection((input, 0, SocketKind::Output), target)
+            .unwrap();
+
+        let knot = tree.insert_reroute(target, (300.0, 200.0).into()).unwrap();
+        let kind = tree.node(knot).unwrap().kind();
+        assert!(matches!(kind, NodeKind::Reroute(ParamType::f64)));
+        assert_eq!(tree.connections.len(), 2);
+        assert_eq!(
+            tree.connected_output(target),
+            Some((knot, 1, SocketKind::Output).into_output())
+        );
+        assert!(tree.insert_reroute(target, (0.0, 0.0).into()).is_some());
+        assert_eq!(tree.connections.len(), 3);
+
+        // Knot is grabbed between its sockets.
+        assert!(matches!(
+            tree.point_cast((300.0, 200.0).into()),
+            CastResult::Node(x) if x == knot
+        ));
+        assert!(matches!(
+            tree.point_cast((288.0, 200.0).into()),
+            CastResult::Socket(..)
+        ));
+    }
+}
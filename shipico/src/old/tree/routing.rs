@@ -0,0 +1,411 @@
+//! Geometry of the connections.
+//!
+//! Wires always follow the current socket positions: they are recomputed
+//! whenever a node they are attached to moves. Orthogonal routing also steers
+//! wires around the nodes, so moving, adding or removing a node recomputes the
+//! wires passing by it as well.
+//!
+//! Orthogonal routes are found on a sparse grid made of the lines along the
+//! sides of the nearby nodes: the cheapest path through the grid is taken,
+//! with every bend costing extra, so wires don't zigzag.
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashSet},
+};
+
+use crate::math::{AsLine, BezierSegment, Line, Point, Rect};
+
+use super::{
+    connection_curve, AsSocketId, Connection, InputSocketId, NodeId, NodeKind, OutputSocketId,
+    SpatialItem, Tree, CONNECTION_TOLERANCE,
+};
+
+/// How connections are laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Routing {
+    /// Bezier curves going straight from the output to the input.
+    #[default]
+    Curved,
+    /// Horizontal and vertical segments going around the nodes.
+    Orthogonal,
+}
+
+/// Shape of the connection in canvas space.
+#[derive(Clone, Debug)]
+pub(super) enum Wire {
+    Curve {
+        start: Point,
+        curve: BezierSegment,
+    },
+    /// Open polyline from the output to the input.
+    Polyline(Vec<Point>),
+}
+
+impl Wire {
+    pub(super) fn bound_rect(&self) -> Rect {
+        match self {
+            Wire::Curve { start, curve } => curve.bound_rect(*start),
+            Wire::Polyline(points) => points[1..]
+                .iter()
+                .fold(Rect::from_points(points[0], points[0]), |rect, &x| {
+                    rect.combined_with(Rect::from_points(x, x))
+                }),
+        }
+    }
+
+    pub(super) fn distance_to(&self, point: Point) -> f64 {
+        match self {
+            Wire::Curve { start, curve } => curve.distance_to(*start, point, CONNECTION_TOLERANCE),
+            Wire::Polyline(_) => self
+                .lines()
+                .iter()
+                .map(|x| x.distance_to(point))
+                .fold(f64::INFINITY, f64::min),
+        }
+    }
+
+    pub(super) fn intersects(&self, line: Line) -> bool {
+        self.lines().into_iter().any(|x| x.is_intersect(line))
+    }
+
+    fn lines(&self) -> Vec<Line> {
+        match self {
+            Wire::Curve { start, curve } => curve.flatten(*start, CONNECTION_TOLERANCE),
+            Wire::Polyline(points) => points
+                .windows(2)
+                .map(|x| Line {
+                    start: x[0],
+                    end: x[1],
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Clearance kept between orthogonal wires and the nodes.
+const ROUTE_MARGIN: f64 = 15.0;
+/// Straight part of orthogonal wires leaving the output and entering the input.
+/// Longer than the margin, so it always gets out of the node clearance.
+const STUB_LENGTH: f64 = 25.0;
+/// Cost of a bend in units of the wire length.
+const BEND_COST: f64 = 60.0;
+/// How far around the sockets nodes are considered as obstacles.
+const ROUTE_AREA: f64 = 300.0;
+
+impl Tree {
+    pub fn routing(&self) -> Routing {
+        self.routing
+    }
+
+    /// Switches routing of the tree along with the groups inside of it.
+    pub fn set_routing(&mut self, routing: Routing) {
+        self.routing = routing;
+        for slot in &mut self.nodes {
+            if let Some(NodeKind::Group(group)) = slot.node.as_mut().map(|x| &mut x.kind) {
+                group.tree.set_routing(routing);
+            }
+        }
+        self.update_wires(|_| true);
+    }
+
+    /// Wire from the output to the input at their current positions.
+    pub(super) fn wire(&self, output: OutputSocketId, input: InputSocketId) -> Wire {
+        let from = self.socket_position(output);
+        let to = self.socket_position(input);
+        match self.routing {
+            Routing::Curved => Wire::Curve {
+                start: from,
+                curve: connection_curve(from, to),
+            },
+            Routing::Orthogonal => Wire::Polyline(self.orthogonal_route(from, to)),
+        }
+    }
+
+    /// Recomputes wires of the connections for which `affected` returns `true`.
+    fn update_wires(&mut self, affected: impl Fn(&Connection) -> bool) {
+        let wires = self
+            .connections
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| affected(x))
+            .map(|(i, x)| (i, self.wire(x.output, x.input)))
+            .collect::<Vec<_>>();
+        for (i, wire) in wires {
+            let connection = &mut self.connections[i];
+            connection.wire = wire;
+            let rect = connection.bound_rect();
+            self.index
+                .insert(SpatialItem::Connection(connection.input), rect);
+        }
+    }
+
+    /// Recomputes wires after the node changed, `rects` are the areas the
+    /// node covered before and after the change.
+    pub(super) fn update_wires_around(&mut self, node: NodeId, rects: &[Rect]) {
+        let passing = match self.routing {
+            Routing::Curved => HashSet::new(),
+            Routing::Orthogonal => rects
+                .iter()
+                .flat_map(|&rect| self.index.query_rect(rect.expanded_by(ROUTE_MARGIN)))
+                .filter_map(|item| match item {
+                    SpatialItem::Connection(input) => Some(input),
+                    SpatialItem::Node(_) => None,
+                })
+                .collect(),
+        };
+        self.update_wires(|x| {
+            x.input.node() == node || x.output.node() == node || passing.contains(&x.input)
+        });
+    }
+
+    /// Path from the output to the input made of horizontal and vertical
+    /// segments, going around the nodes where it can.
+    fn orthogonal_route(&self, from: Point, to: Point) -> Vec<Point> {
+        let start = from + [STUB_LENGTH, 0.0];
+        let end = to - [STUB_LENGTH, 0.0];
+        let area = Rect::from_points(start, end).expanded_by(ROUTE_AREA);
+        // Nodes touching the sockets can't be avoided anyway.
+        let obstacles = self
+            .index
+            .query_rect(area)
+            .into_iter()
+            .filter_map(|item| match item {
+                SpatialItem::Node(id) => self.node(id),
+                SpatialItem::Connection(_) => None,
+            })
+            .filter(|x| !x.is_annotation())
+            .map(|x| x.bound_rect().expanded_by(ROUTE_MARGIN))
+            .filter(|x| !is_inside(x, start) && !is_inside(x, end))
+            .collect::<Vec<_>>();
+
+        let path = shortest_path(&obstacles, start, end).unwrap_or_else(|| {
+            let middle = (start.x + end.x) / 2.0;
+            vec![
+                start,
+                Point::new(middle, start.y),
+                Point::new(middle, end.y),
+                end,
+            ]
+        });
+        let mut points = vec![from];
+        points.extend(path);
+        points.push(to);
+        simplify(points)
+    }
+}
+
+/// Whether the point is strictly inside of the rect, borders don't count.
+fn is_inside(rect: &Rect, point: Point) -> bool {
+    point.x > rect.left && point.x < rect.right && point.y > rect.top && point.y < rect.bottom
+}
+
+/// Entry of the search queue, the cheapest one is popped first.
+struct Step {
+    cost: f64,
+    state: usize,
+}
+
+impl PartialEq for Step {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Step {}
+
+impl PartialOrd for Step {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Step {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .partial_cmp(&self.cost)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Dijkstra search on the grid made of the obstacle sides and the ends.
+///
+/// Search state is a grid point along with the direction the path came from,
+/// so bends can be charged for. Path starts and ends going to the right, like
+/// the wires leaving outputs and entering inputs. Returns `None` if the end
+/// can't be reached.
+fn shortest_path(obstacles: &[Rect], start: Point, end: Point) -> Option<Vec<Point>> {
+    let coordinates = |ends: [f64; 2], sides: &dyn Fn(&Rect) -> [f64; 2]| {
+        let mut values = ends.to_vec();
+        values.extend(obstacles.iter().flat_map(|x| sides(x).to_vec()));
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        values.dedup();
+        values
+    };
+    let xs = coordinates([start.x, end.x], &|x| [x.left, x.right]);
+    let ys = coordinates([start.y, end.y], &|x| [x.top, x.bottom]);
+    let find = |values: &[f64], x: f64| values.iter().position(|&y| y == x).unwrap();
+    let point = |cell: usize| Point::new(xs[cell % xs.len()], ys[cell / xs.len()]);
+
+    // Right, left, down, up.
+    const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+    let neighbour = |cell: usize, (dx, dy): (isize, isize)| {
+        let x = (cell % xs.len()) as isize + dx;
+        let y = (cell / xs.len()) as isize + dy;
+        if x < 0 || y < 0 || x >= xs.len() as isize || y >= ys.len() as isize {
+            return None;
+        }
+        let next = y as usize * xs.len() + x as usize;
+        let middle = point(cell) + (point(next) - point(cell)) / 2.0;
+        if obstacles.iter().any(|x| is_inside(x, middle)) {
+            return None;
+        }
+        Some(next)
+    };
+
+    let first = find(&ys, start.y) * xs.len() + find(&xs, start.x);
+    let last = find(&ys, end.y) * xs.len() + find(&xs, end.x);
+    let states = xs.len() * ys.len() * DIRECTIONS.len();
+    let mut costs = vec![f64::INFINITY; states];
+    let mut previous = vec![usize::MAX; states];
+    let mut queue = BinaryHeap::new();
+    costs[first * DIRECTIONS.len()] = 0.0;
+    queue.push(Step {
+        cost: 0.0,
+        state: first * DIRECTIONS.len(),
+    });
+
+    let mut goal = None;
+    while let Some(Step { cost, state }) = queue.pop() {
+        if cost > costs[state] {
+            continue;
+        }
+        let (cell, direction) = (state / DIRECTIONS.len(), state % DIRECTIONS.len());
+        if cell == last && direction == 0 {
+            goal = Some(state);
+            break;
+        }
+        for (next_direction, &delta) in DIRECTIONS.iter().enumerate() {
+            let next = match neighbour(cell, delta) {
+                Some(next) => next,
+                None => continue,
+            };
+            let bend = if next_direction == direction {
+                0.0
+            } else {
+                BEND_COST
+            };
+            let cost = cost + (point(next) - point(cell)).len() + bend;
+            // Arriving at the end not going right needs one more bend.
+            let cost = if next == last && next_direction != 0 {
+                cost + BEND_COST
+            } else {
+                cost
+            };
+            let next_state =
+                next * DIRECTIONS.len() + if next == last { 0 } else { next_direction };
+            if cost < costs[next_state] {
+                costs[next_state] = cost;
+                previous[next_state] = state;
+                queue.push(Step {
+                    cost,
+                    state: next_state,
+                });
+            }
+        }
+    }
+
+    let mut path = Vec::new();
+    let mut state = goal?;
+    loop {
+        path.push(point(state / DIRECTIONS.len()));
+        if previous[state] == usize::MAX {
+            break;
+        }
+        state = previous[state];
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Drops repeated points and the points in the middle of straight segments.
+fn simplify(points: Vec<Point>) -> Vec<Point> {
+    let mut result: Vec<Point> = Vec::with_capacity(points.len());
+    for point in points {
+        if result.last() == Some(&point) {
+            continue;
+        }
+        if let [.., a, b] = result[..] {
+            let straight = (a.x == b.x && b.x == point.x) || (a.y == b.y && b.y == point.y);
+            if straight {
+                result.pop();
+            }
+        }
+        result.push(point);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::{tests::function, CastResult, SocketKind};
+
+    use super::*;
+
+    #[test]
+    fn wires_follow_nodes() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (400.0, 0.0).into());
+        let target = (output, 0, SocketKind::Input).into_input();
+        tree.create_connection((input, 0, SocketKind::Output), target)
+            .unwrap();
+
+        tree.move_node(output, [0.0, 300.0].into());
+        let end = tree.socket_position(target);
+        match &tree.connection(target).unwrap().wire {
+            Wire::Curve { curve, .. } => assert_eq!(curve.p3, end),
+            Wire::Polyline(_) => panic!("curves are the default"),
+        }
+        // Curves are symmetric, so they pass the middle between the sockets.
+        let start = tree.socket_position((input, 0, SocketKind::Output));
+        let middle = start + (end - start) / 2.0;
+        assert!(matches!(tree.point_cast(middle), CastResult::Connection(x) if x == target));
+    }
+
+    #[test]
+    fn orthogonal_wires_avoid_nodes() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (800.0, 0.0).into());
+        let target = (output, 0, SocketKind::Input).into_input();
+        tree.create_connection((input, 0, SocketKind::Output), target)
+            .unwrap();
+        tree.set_routing(Routing::Orthogonal);
+
+        let obstacle = tree.create_node(function("boolean"), (400.0, 0.0).into());
+        let rect = tree.node(obstacle).unwrap().bound_rect();
+        let route = |tree: &Tree| match &tree.connection(target).unwrap().wire {
+            Wire::Polyline(points) => points.clone(),
+            Wire::Curve { .. } => panic!("routing is orthogonal"),
+        };
+        let points = route(&tree);
+        assert_eq!(
+            points.first(),
+            Some(&tree.socket_position((input, 0, SocketKind::Output)))
+        );
+        assert_eq!(points.last(), Some(&tree.socket_position(target)));
+        for line in points.windows(2) {
+            assert!(line[0].x == line[1].x || line[0].y == line[1].y);
+            let middle = line[0] + (line[1] - line[0]) / 2.0;
+            assert!(!is_inside(&rect, middle));
+        }
+        assert!(points.len() > 2);
+
+        // Wire straightens up once the node is out of the way.
+        tree.move_node(obstacle, [0.0, 1000.0].into());
+        let start = tree.socket_position((input, 0, SocketKind::Output));
+        assert_eq!(route(&tree), vec![start, tree.socket_position(target)]);
+    }
+}
@@ -0,0 +1,197 @@
+//! Nodes user operates on at once.
+
+use std::collections::HashMap;
+
+use crate::math::{Point, Rect, Vec2};
+
+use super::{AsSocketId, NodeData, NodeId, NodeKind, SpatialItem, Tree};
+
+impl Tree {
+    /// Selected nodes in the order they were selected.
+    pub fn selection(&self) -> &[NodeId] {
+        &self.selection
+    }
+
+    pub fn is_selected(&self, node: NodeId) -> bool {
+        self.selection.contains(&node)
+    }
+
+    /// Adds node to the selection, if it is not selected yet.
+    pub fn select(&mut self, node: NodeId) {
+        if self.node(node).is_some() && !self.is_selected(node) {
+            self.selection.push(node);
+        }
+    }
+
+    /// Adds node to the selection or removes it if it is selected already.
+    pub fn toggle_selection(&mut self, node: NodeId) {
+        if self.is_selected(node) {
+            self.deselect(node);
+        } else {
+            self.select(node);
+        }
+    }
+
+    pub fn deselect(&mut self, node: NodeId) {
+        self.selection.retain(|&x| x != node);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection.clear();
+    }
+
+    /// Returns nodes overlapping the rectangle in screen space.
+    pub fn rect_cast(&self, rect: Rect) -> Vec<NodeId> {
+        let rect = Rect::from_points(
+            self.screen_to_canvas((rect.left, rect.top)),
+            self.screen_to_canvas((rect.right, rect.bottom)),
+        );
+        let mut nodes = self
+            .index
+            .query_rect(rect)
+            .into_iter()
+            .filter_map(|item| match item {
+                SpatialItem::Node(id) => Some(id),
+                SpatialItem::Connection(_) => None,
+            })
+            .collect::<Vec<_>>();
+        // Keep the drawing order.
+        nodes.sort_unstable_by_key(|id| id.index);
+        nodes
+    }
+
+    /// Copies nodes along with the connections between them and selects the copies.
+    ///
+    /// Copies are moved by offset in canvas space. Returns ids of the copies,
+    /// stale ids are skipped.
+    pub fn duplicate_nodes(&mut self, nodes: &[NodeId], offset: Vec2) -> Vec<NodeId> {
+        let copies = nodes
+            .iter()
+            .filter_map(|&id| {
+                let node = self.node(id)?;
+                let mut copy = NodeData::new(node.kind.clone());
+                copy.position = node.position + offset;
+                Some((id, self.insert_node(copy)))
+            })
+            .collect::<Vec<_>>();
+        let copy_of = |id: NodeId| {
+            copies
+                .iter()
+                .find(|(original, _)| *original == id)
+                .map(|&(_, copy)| copy)
+        };
+
+        let connections = self
+            .connections
+            .iter()
+            .filter_map(|x| {
+                let input = copy_of(x.input.node())?;
+                let output = copy_of(x.output.node())?;
+                Some((
+                    (input, x.input.id(), x.input.kind()).into_input(),
+                    (output, x.output.id(), x.output.kind()).into_output(),
+                ))
+            })
+            .collect::<Vec<_>>();
+        for (input, output) in connections {
+            self.connect_sockets(input, output);
+        }
+
+        self.selection = copies.iter().map(|&(_, copy)| copy).collect();
+        self.selection.clone()
+    }
+
+    /// Inserts all nodes of the fragment with fresh ids and selects them.
+    ///
+    /// Fragment node positions are treated as relative to the `position`
+    /// in screen space, see [`Tree::save_fragment`].
+    pub fn paste(&mut self, fragment: &Tree, position: Point) -> Vec<NodeId> {
+        let origin = self.screen_to_canvas(position).to_vector();
+        let ids = fragment
+            .nodes()
+            .map(|(id, node)| {
+                let mut copy = node.clone();
+                copy.sockets.iter_mut().for_each(|x| x.enabled = false);
+                copy.position += origin;
+                if let NodeKind::Group(group) = &mut copy.kind {
+                    group.tree.set_routing(self.routing);
+                }
+                (id, self.insert_node(copy))
+            })
+            .collect::<HashMap<_, _>>();
+
+        for connection in &fragment.connections {
+            let input = connection.input;
+            let output = connection.output;
+            self.connect_sockets(
+                (ids[&input.node()], input.id(), input.kind()).into_input(),
+                (ids[&output.node()], output.id(), output.kind()).into_output(),
+            );
+        }
+
+        self.selection = fragment.nodes().map(|(id, _)| ids[&id]).collect();
+        self.selection.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::{tests::function, SocketKind};
+
+    use super::*;
+
+    #[test]
+    fn duplicate_selection() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (0.0, 200.0).into());
+        let other = tree.create_node(function("output_geo"), (500.0, 200.0).into());
+        tree.create_connection(
+            (input, 0, SocketKind::Output),
+            (output, 0, SocketKind::Input),
+        )
+        .unwrap();
+        tree.create_connection(
+            (input, 0, SocketKind::Output),
+            (other, 0, SocketKind::Input),
+        )
+        .unwrap();
+
+        let selected = tree.rect_cast(Rect::new(-50.0, -50.0, 50.0, 250.0));
+        assert_eq!(selected, vec![input, output]);
+        selected.into_iter().for_each(|x| tree.select(x));
+
+        let selection = tree.selection().to_vec();
+        let copies = tree.duplicate_nodes(&selection, [300.0, 0.0].into());
+        assert_eq!(copies.len(), 2);
+        assert_eq!(tree.selection(), copies.as_slice());
+        assert_eq!(tree.node(copies[0]).unwrap().position, (300.0, 0.0).into());
+        // Only the connection between duplicated nodes is copied.
+        assert_eq!(tree.connections.len(), 3);
+
+        tree.remove_node(copies[1]);
+        assert_eq!(tree.selection(), &copies[..1]);
+    }
+
+    #[test]
+    fn paste() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (0.0, 200.0).into());
+        tree.create_connection(
+            (input, 0, SocketKind::Output),
+            (output, 0, SocketKind::Input),
+        )
+        .unwrap();
+        let fragment = Tree::load_fragment(&tree.save_fragment(&[input, output])).unwrap();
+
+        let pasted = tree.paste(&fragment, (1000.0, 1000.0).into());
+        assert_eq!(tree.selection(), pasted.as_slice());
+        assert!(!pasted.contains(&input) && !pasted.contains(&output));
+        assert_eq!(
+            tree.node(pasted[0]).unwrap().position,
+            (1000.0, 900.0).into()
+        );
+        assert_eq!(tree.connections.len(), 2);
+    }
+}
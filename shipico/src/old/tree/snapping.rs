@@ -0,0 +1,156 @@
+//! Snapping of the dragged nodes to the grid and to the nearby nodes.
+
+use crate::math::{Line, Rect, Vec2};
+
+use super::{NodeId, SpatialItem, Tree};
+
+/// Steps of the minor and the major lines of the grid the workbench draws.
+pub const GRID_STEPS: [f64; 2] = [10.0, 50.0];
+
+/// How dragged nodes are snapped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Snapping {
+    /// Step of the grid node positions snap to, `None` turns the grid off.
+    pub grid: Option<f64>,
+    /// Whether edges and centers of the nodes snap to the nearby nodes.
+    pub guides: bool,
+}
+
+impl Snapping {
+    /// Nodes follow the mouse exactly.
+    pub const NONE: Snapping = Snapping {
+        grid: None,
+        guides: false,
+    };
+}
+
+impl Default for Snapping {
+    fn default() -> Self {
+        Snapping {
+            grid: Some(GRID_STEPS[0]),
+            guides: true,
+        }
+    }
+}
+
+/// How close in screen space edges have to be to snap to each other.
+const GUIDE_DISTANCE: f64 = 8.0;
+/// How far in screen space nodes are looked for to align with.
+const GUIDE_RANGE: f64 = 600.0;
+
+impl Tree {
+    /// Snaps the offset in canvas space the nodes are about to be moved by.
+    ///
+    /// Grid snaps the position of the `lead` node, usually the one user
+    /// grabbed. Guides align edges and centers of all the nodes with the
+    /// nearby nodes and take precedence over the grid. Returns the snapped
+    /// offset and the guides to show, in canvas space.
+    pub fn snap(
+        &self,
+        nodes: &[NodeId],
+        lead: NodeId,
+        offset: Vec2,
+        snapping: Snapping,
+    ) -> (Vec2, Vec<Line>) {
+        let mut snapped = offset;
+        if let (Some(step), Some(lead)) = (snapping.grid, self.node(lead)) {
+            let position = lead.position + offset;
+            snapped = Vec2::new(
+                (position.x / step).round() * step - lead.position.x,
+                (position.y / step).round() * step - lead.position.y,
+            );
+        }
+
+        let rect = match nodes
+            .iter()
+            .filter_map(|&x| self.node(x))
+            .map(|x| x.bound_rect())
+            .reduce(|a, b| a.combined_with(b))
+        {
+            Some(rect) if snapping.guides => rect.translated_by(offset),
+            _ => return (snapped, Vec::new()),
+        };
+        let targets = self
+            .index
+            .query_rect(rect.expanded_by(GUIDE_RANGE / self.z()))
+            .into_iter()
+            .filter_map(|item| match item {
+                SpatialItem::Node(id) if !nodes.contains(&id) => self.node(id),
+                _ => None,
+            })
+            .filter(|x| !x.is_frame())
+            .map(|x| x.bound_rect())
+            .collect::<Vec<_>>();
+
+        let threshold = GUIDE_DISTANCE / self.z();
+        let mut guides = Vec::new();
+        // Closest of the edges and centers along one axis, as the adjustment
+        // along with the target it snaps to.
+        let closest = |sides: fn(&Rect) -> [f64; 3]| {
+            let moving = sides(&rect);
+            let mut best: Option<(f64, Rect, f64)> = None;
+            for target in &targets {
+                for &x in &sides(target) {
+                    for &y in &moving {
+                        let delta = x - y;
+                        let closer = best.is_none_or(|(best, _, _)| delta.abs() < best.abs());
+                        if delta.abs() <= threshold && closer {
+                            best = Some((delta, *target, x));
+                        }
+                    }
+                }
+            }
+            best
+        };
+
+        if let Some((delta, target, x)) = closest(|r| [r.left, r.center().x, r.right]) {
+            snapped.x = offset.x + delta;
+            let (top, bottom) = (rect.top.min(target.top), rect.bottom.max(target.bottom));
+            guides.push(Line {
+                start: (x, top).into(),
+                end: (x, bottom).into(),
+            });
+        }
+        if let Some((delta, target, y)) = closest(|r| [r.top, r.center().y, r.bottom]) {
+            snapped.y = offset.y + delta;
+            let (left, right) = (rect.left.min(target.left), rect.right.max(target.right));
+            guides.push(Line {
+                start: (left, y).into(),
+                end: (right, y).into(),
+            });
+        }
+        (snapped, guides)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::tests::function;
+
+    use super::*;
+
+    #[test]
+    fn snapping() {
+        let mut tree = Tree::new();
+        let node = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+
+        let grid = Snapping {
+            grid: Some(GRID_STEPS[1]),
+            guides: true,
+        };
+        let (offset, guides) = tree.snap(&[node], node, [23.0, 31.0].into(), grid);
+        assert_eq!(offset, [0.0, 50.0].into());
+        assert!(guides.is_empty());
+        let (offset, _) = tree.snap(&[node], node, [23.0, 31.0].into(), Snapping::NONE);
+        assert_eq!(offset, [23.0, 31.0].into());
+
+        // Left edges align, guides beat the grid.
+        let other = tree.create_node(function("boolean"), (300.0, 400.0).into());
+        let left = tree.node(other).unwrap().bound_rect().left;
+        let offset = left - tree.node(node).unwrap().bound_rect().left;
+        let (snapped, guides) = tree.snap(&[node], node, [offset + 3.0, 200.0].into(), grid);
+        assert_eq!(snapped, [offset, 200.0].into());
+        assert_eq!(guides.len(), 1);
+        assert_eq!((guides[0].start.x, guides[0].end.x), (left, left));
+    }
+}
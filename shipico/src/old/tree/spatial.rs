@@ -0,0 +1,340 @@
+//! Spatial index of the tree, so casts don't have to check every node
+//! and connection.
+//!
+//! Index is a loose quadtree: every item is stored in the deepest cell that
+//! contains its center and is not smaller than the item itself. Cells are
+//! tested with bounds expanded by half of their size, which covers every
+//! item stored in them, so items never have to be split between cells.
+
+use std::{collections::HashMap, hash::Hash};
+
+use crate::math::{Line, Point, Rect};
+
+use super::{InputSocketId, NodeId};
+
+/// Everything casts can hit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(super) enum SpatialItem {
+    Node(NodeId),
+    Connection(InputSocketId),
+}
+
+#[derive(Clone)]
+pub struct QuadTree<T> {
+    /// Cells of the tree, root is the first one.
+    cells: Vec<Cell<T>>,
+    /// Cell of every item.
+    locations: HashMap<T, usize>,
+}
+
+#[derive(Clone)]
+struct Cell<T> {
+    bounds: Rect,
+    depth: u32,
+    /// Children are created the first time an item goes deeper than this cell.
+    children: Option<[usize; 4]>,
+    items: Vec<(T, Rect)>,
+}
+
+impl<T> Default for QuadTree<T> {
+    fn default() -> Self {
+        QuadTree {
+            cells: vec![Cell {
+                bounds: Rect::new(-Self::EXTENT, -Self::EXTENT, Self::EXTENT, Self::EXTENT),
+                depth: 0,
+                children: None,
+                items: Vec::new(),
+            }],
+            locations: HashMap::new(),
+        }
+    }
+}
+
+impl<T> QuadTree<T> {
+    /// Half of the root cell size. Items outside of the root cell are kept in the root.
+    const EXTENT: f64 = 65536.0;
+    const MAX_DEPTH: u32 = 12;
+}
+
+impl<T: Copy + Eq + Hash> QuadTree<T> {
+    pub fn new() -> QuadTree<T> {
+        QuadTree::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// Inserts item or moves it, if it is already in the tree.
+    pub fn insert(&mut self, item: T, rect: Rect) {
+        self.remove(item);
+        let rect = rect.normalized();
+        let cell = self.cell_for(rect);
+        self.cells[cell].items.push((item, rect));
+        self.locations.insert(item, cell);
+    }
+
+    /// Returns `false` if there was no such item.
+    pub fn remove(&mut self, item: T) -> bool {
+        match self.locations.remove(&item) {
+            Some(cell) => {
+                let items = &mut self.cells[cell].items;
+                if let Some(i) = items.iter().position(|&(x, _)| x == item) {
+                    items.swap_remove(i);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Items which rects contain the point.
+    pub fn query_point(&self, point: impl Into<Point>) -> Vec<T> {
+        let point = point.into();
+        self.query(|rect| rect.contains_point(point))
+    }
+
+    /// Items which rects overlap the rect.
+    pub fn query_rect(&self, rect: Rect) -> Vec<T> {
+        let rect = rect.normalized();
+        self.query(|x| x.overlaps(&rect))
+    }
+
+    /// Items which rects are crossed by the segment.
+    pub fn query_segment(&self, line: Line) -> Vec<T> {
+        self.query(|rect| segment_crosses(line, rect))
+    }
+
+    /// Finds the deepest cell the rect fits into, creating cells on the way.
+    fn cell_for(&mut self, rect: Rect) -> usize {
+        let size = (rect.right - rect.left).max(rect.bottom - rect.top);
+        let center = rect.center();
+
+        let mut cell = 0;
+        loop {
+            let Cell { bounds, depth, .. } = self.cells[cell];
+            let child_size = (bounds.right - bounds.left) / 2.0;
+            if depth == Self::MAX_DEPTH || size > child_size || !bounds.contains_point(center) {
+                return cell;
+            }
+
+            let children = match self.cells[cell].children {
+                Some(children) => children,
+                None => self.split(cell),
+            };
+            let middle = bounds.center();
+            let quadrant = (center.x >= middle.x) as usize + 2 * (center.y >= middle.y) as usize;
+            cell = children[quadrant];
+        }
+    }
+
+    fn split(&mut self, cell: usize) -> [usize; 4] {
+        let Cell { bounds, depth, .. } = self.cells[cell];
+        let middle = bounds.center();
+        let first = self.cells.len();
+        for &(left, top, right, bottom) in &[
+            (bounds.left, bounds.top, middle.x, middle.y),
+            (middle.x, bounds.top, bounds.right, middle.y),
+            (bounds.left, middle.y, middle.x, bounds.bottom),
+            (middle.x, middle.y, bounds.right, bounds.bottom),
+        ] {
+            self.cells.push(Cell {
+                bounds: Rect::new(left, top, right, bottom),
+                depth: depth + 1,
+                children: None,
+                items: Vec::new(),
+            });
+        }
+
+        let children = [first, first + 1, first + 2, first + 3];
+        self.cells[cell].children = Some(children);
+        children
+    }
+
+    /// Collects items which rects pass the test, skipping cells that can't
+    /// contain such items.
+    fn query(&self, test: impl Fn(&Rect) -> bool) -> Vec<T> {
+        let mut result = Vec::new();
+        let mut stack = vec![0];
+        while let Some(cell) = stack.pop() {
+            let cell = &self.cells[cell];
+            // Root also keeps the items outside of its bounds.
+            if cell.depth > 0 {
+                let half = (cell.bounds.right - cell.bounds.left) / 2.0;
+                if !test(&cell.bounds.expanded_by(half)) {
+                    continue;
+                }
+            }
+
+            result.extend(
+                cell.items
+                    .iter()
+                    .filter(|(_, rect)| test(rect))
+                    .map(|&(item, _)| item),
+            );
+            if let Some(children) = cell.children {
+                stack.extend_from_slice(&children);
+            }
+        }
+        result
+    }
+}
+
+/// Checks whether segment crosses or lies inside the rect (Liang-Barsky clipping).
+fn segment_crosses(line: Line, rect: &Rect) -> bool {
+    let start = line.start;
+    let delta = line.end - line.start;
+    let mut enter: f64 = 0.0;
+    let mut exit: f64 = 1.0;
+    for &(p, q) in &[
+        (-delta.x, start.x - rect.left),
+        (delta.x, rect.right - start.x),
+        (-delta.y, start.y - rect.top),
+        (delta.y, rect.bottom - start.y),
+    ] {
+        if p == 0.0 {
+            // Parallel to the edge and outside of it.
+            if q < 0.0 {
+                return false;
+            }
+        } else if p < 0.0 {
+            enter = enter.max(q / p);
+        } else {
+            exit = exit.min(q / p);
+        }
+        if enter > exit {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use test::Bencher;
+
+    use super::*;
+
+    /// Node-sized rects laid out in a grid, like the demo tree but bigger.
+    fn grid(side: usize) -> Vec<(usize, Rect)> {
+        (0..side * side)
+            .map(|i| {
+                let (x, y) = ((i % side) as f64 * 250.0, (i / side) as f64 * 150.0);
+                (i, Rect::new(x, y, x + 200.0, y + 100.0))
+            })
+            .collect()
+    }
+
+    fn index(items: &[(usize, Rect)]) -> QuadTree<usize> {
+        let mut index = QuadTree::new();
+        for &(item, rect) in items {
+            index.insert(item, rect);
+        }
+        index
+    }
+
+    fn sorted(mut items: Vec<usize>) -> Vec<usize> {
+        items.sort_unstable();
+        items
+    }
+
+    #[test]
+    fn queries_match_linear_scan() {
+        let items = grid(20);
+        let mut index = index(&items);
+        let linear = |test: &dyn Fn(&Rect) -> bool| {
+            items
+                .iter()
+                .filter(|(_, rect)| test(rect))
+                .map(|&(item, _)| item)
+                .collect::<Vec<_>>()
+        };
+
+        for &point in &[(0.0, 0.0), (210.0, 50.0), (1030.0, 1520.0), (-5.0, 3.0)] {
+            let expected = linear(&|rect| rect.contains_point(point));
+            assert_eq!(sorted(index.query_point(point)), expected);
+        }
+
+        let rect = Rect::new(300.0, 200.0, 1300.0, 700.0);
+        let expected = linear(&|x| x.overlaps(&rect));
+        assert_eq!(sorted(index.query_rect(rect)), expected);
+
+        let line = Line {
+            start: (-100.0, -100.0).into(),
+            end: (3000.0, 2000.0).into(),
+        };
+        let expected = linear(&|rect| segment_crosses(line, rect));
+        assert!(!expected.is_empty());
+        assert_eq!(sorted(index.query_segment(line)), expected);
+
+        index.insert(0, Rect::new(5000.0, 5000.0, 5200.0, 5100.0));
+        assert!(index.query_point((10.0, 10.0)).is_empty());
+        assert_eq!(index.query_point((5100.0, 5050.0)), vec![0]);
+        assert!(index.remove(0));
+        assert!(!index.remove(0));
+        assert_eq!(index.len(), items.len() - 1);
+    }
+
+    #[test]
+    fn items_outside_of_root() {
+        let mut index = QuadTree::new();
+        let far = QuadTree::<usize>::EXTENT * 4.0;
+        index.insert(1, Rect::new(far, far, far + 10.0, far + 10.0));
+        assert_eq!(index.query_point((far + 5.0, far + 5.0)), vec![1]);
+    }
+
+    // 1000 nodes, the size of the graphs we actually have.
+    const BENCH_SIDE: usize = 32;
+
+    #[bench]
+    fn point_query_quadtree(b: &mut Bencher) {
+        let index = index(&grid(BENCH_SIDE));
+        b.iter(|| index.query_point(test::black_box((4010.0, 2350.0))));
+    }
+
+    #[bench]
+    fn point_query_linear(b: &mut Bencher) {
+        let items = grid(BENCH_SIDE);
+        b.iter(|| {
+            let point = test::black_box((4010.0, 2350.0));
+            items
+                .iter()
+                .filter(|(_, rect)| rect.contains_point(point))
+                .map(|&(item, _)| item)
+                .collect::<Vec<_>>()
+        });
+    }
+
+    #[bench]
+    fn segment_query_quadtree(b: &mut Bencher) {
+        let index = index(&grid(BENCH_SIDE));
+        let line = Line {
+            start: (1000.0, 1000.0).into(),
+            end: (1600.0, 1400.0).into(),
+        };
+        b.iter(|| index.query_segment(test::black_box(line)));
+    }
+
+    #[bench]
+    fn segment_query_linear(b: &mut Bencher) {
+        let items = grid(BENCH_SIDE);
+        let line = Line {
+            start: (1000.0, 1000.0).into(),
+            end: (1600.0, 1400.0).into(),
+        };
+        b.iter(|| {
+            let line = test::black_box(line);
+            items
+                .iter()
+                .filter(|(_, rect)| segment_crosses(line, rect))
+                .map(|&(item, _)| item)
+                .collect::<Vec<_>>()
+        });
+    }
+}
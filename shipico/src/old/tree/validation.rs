@@ -0,0 +1,269 @@
+//! Checks that tell what's wrong with the tree before it is run.
+
+use std::collections::HashSet;
+
+use crate::{function::FunctionDefinition, params::ParamType};
+
+use super::{AsSocketId, InputSocketId, NodeId, NodeKind, SocketId, SocketKind, Tree};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Tree runs, but probably not the way user wants.
+    Warning,
+    /// Tree can't run.
+    Error,
+}
+
+/// What's wrong with the node or the socket.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    /// Input has no value, nothing is connected to it and it has no default.
+    UnconnectedInput {
+        name: String,
+    },
+    /// Group output has no value, the inner node providing it was removed.
+    MissingGroupOutput {
+        name: String,
+    },
+    TypeMismatch {
+        output: ParamType,
+        input: ParamType,
+    },
+    /// Node refers to the function missing from the `FunctionRegistry`, or
+    /// registered with different inputs or outputs.
+    UnknownFunction {
+        name: String,
+    },
+    /// Nothing node computes ever reaches a node without outputs, so it is
+    /// computed for nothing.
+    Unreachable,
+    /// Function failed when the tree was evaluated.
+    Failed {
+        message: String,
+    },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Problem::UnconnectedInput { name } => write!(f, "input `{}` is not connected", name),
+            Problem::MissingGroupOutput { name } => {
+                write!(f, "group output `{}` has no source", name)
+            }
+            Problem::TypeMismatch { output, input } => {
+                write!(f, "`{}` output is connected to `{}` input", output, input)
+            }
+            Problem::UnknownFunction { name } => write!(f, "unknown function `{}`", name),
+            Problem::Unreachable => write!(f, "result is never used"),
+            Problem::Failed { message } => write!(f, "failed: {}", message),
+        }
+    }
+}
+
+/// Problem found by [`Tree::validate`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Groups from the validated tree down to the tree the node is in,
+    /// empty for the nodes of the validated tree.
+    pub path: Vec<NodeId>,
+    pub node: NodeId,
+    /// Offending socket, `None` if the problem is with the whole node.
+    pub socket: Option<SocketId>,
+    pub problem: Problem,
+}
+
+impl Problem {
+    fn severity(&self) -> Severity {
+        match self {
+            Problem::Unreachable => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl Tree {
+    /// Finds problems in the tree and in the groups inside of it, errors first.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        self.validate_in(&mut Vec::new(), &HashSet::new(), &[], &mut diagnostics);
+        diagnostics.sort_by_key(|x| std::cmp::Reverse(x.severity));
+        diagnostics
+    }
+
+    /// Validates tree at the `path`. Inputs in `fed` receive values of the
+    /// group inputs and sockets in `results` provide values of the group
+    /// outputs, both are empty for the root tree.
+    fn validate_in(
+        &self,
+        path: &mut Vec<NodeId>,
+        fed: &HashSet<InputSocketId>,
+        results: &[SocketId],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let mut report = |node: NodeId, socket: Option<SocketId>, problem: Problem| {
+            diagnostics.push(Diagnostic {
+                severity: problem.severity(),
+                path: path.clone(),
+                node,
+                socket,
+                problem,
+            })
+        };
+
+        for (id, node) in self.nodes() {
+            let socket = |kind, index| {
+                node.socket_id(kind, index)
+                    .map(|x| (id, x, kind).into_generic())
+            };
+            if let NodeKind::Function(function) = &node.kind {
+                let known = FunctionDefinition::find(function.name)
+                    .is_some_and(|x| x.inputs == function.inputs && x.outputs == function.outputs);
+                if !known {
+                    let name = function.name.to_string();
+                    report(id, None, Problem::UnknownFunction { name });
+                }
+            }
+
+            for index in 0..node.kind.inputs().len() {
+                let input = socket(SocketKind::Input, index).unwrap();
+                if self.connected_output(input.into_input()).is_none()
+                    && !fed.contains(&input.into_input())
+                    && node.input_value(index).is_none()
+                {
+                    let name = node.kind.input_name(index);
+                    report(id, Some(input), Problem::UnconnectedInput { name });
+                }
+            }
+
+            if let NodeKind::Group(group) = &node.kind {
+                for index in 0..group.outputs.len() {
+                    if group.output_source(index).is_none() {
+                        let name = group.output_name(index);
+                        let output = socket(SocketKind::Output, index);
+                        report(id, output, Problem::MissingGroupOutput { name });
+                    }
+                }
+            }
+        }
+
+        for connection in &self.connections {
+            let output = self.socket_type(connection.output);
+            let input = self.socket_type(connection.input);
+            if output != input {
+                let socket = Some(connection.input.into_generic());
+                report(
+                    connection.input.node(),
+                    socket,
+                    Problem::TypeMismatch { output, input },
+                );
+            }
+        }
+
+        // Nodes without outputs and nodes providing group outputs are where
+        // the results go, everything else has to lead to them.
+        let mut used = HashSet::new();
+        let mut stack = self
+            .nodes()
+            .filter(|(id, node)| {
+                !node.is_annotation()
+                    && (node.kind.outputs().is_empty() || results.iter().any(|x| x.node() == *id))
+            })
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            if used.insert(node) {
+                stack.extend(
+                    self.connections
+                        .iter()
+                        .filter(|x| x.input.node() == node)
+                        .map(|x| x.output.node()),
+                );
+            }
+        }
+        for (id, node) in self.nodes() {
+            if !node.is_annotation() && !used.contains(&id) {
+                report(id, None, Problem::Unreachable);
+            }
+        }
+
+        for (id, node) in self.nodes() {
+            if let NodeKind::Group(group) = &node.kind {
+                let fed = (0..group.inputs.len())
+                    .flat_map(|x| group.input_targets(x))
+                    .collect();
+                let results = (0..group.outputs.len())
+                    .filter_map(|x| group.output_source(x))
+                    .map(|x| x.into_generic())
+                    .collect::<Vec<_>>();
+                path.push(id);
+                group.tree.validate_in(path, &fed, &results, diagnostics);
+                path.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::tests::{function, socket};
+
+    use super::*;
+
+    #[test]
+    fn validation() {
+        let mut tree = Tree::new();
+        let input = tree.create_node(function("input_geo"), (0.0, 0.0).into());
+        let boolean = tree.create_node(function("boolean"), (300.0, 0.0).into());
+        let output = tree.create_node(function("output_geo"), (600.0, 0.0).into());
+        tree.create_connection(
+            socket(input, 0, SocketKind::Output),
+            socket(boolean, 0, SocketKind::Input),
+        )
+        .unwrap();
+        tree.create_connection(
+            socket(boolean, 2, SocketKind::Output),
+            socket(output, 0, SocketKind::Input),
+        )
+        .unwrap();
+
+        let diagnostics = tree.validate();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].node, boolean);
+        assert_eq!(
+            diagnostics[0].socket,
+            Some(socket(boolean, 1, SocketKind::Input))
+        );
+
+        tree.create_connection(
+            socket(input, 0, SocketKind::Output),
+            socket(boolean, 1, SocketKind::Input),
+        )
+        .unwrap();
+        assert!(tree.validate().is_empty());
+
+        // Inputs fed by the group are connected and its output is used.
+        let (group, _) = tree.collapse(&[boolean]).unwrap();
+        assert!(tree.validate().is_empty());
+
+        // Removing the output leaves the rest unused.
+        tree.remove_node(output);
+        let diagnostics = tree.validate();
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().any(|x| x.node == group));
+        assert!(diagnostics.iter().all(|x| x.path.is_empty()
+            && x.problem == Problem::Unreachable
+            && x.severity == Severity::Warning));
+
+        let mut unknown = function("boolean");
+        unknown.name = "missing";
+        tree.create_node(unknown, (0.0, 300.0).into());
+        assert_eq!(
+            tree.validate()[0].problem,
+            Problem::UnknownFunction {
+                name: "missing".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Values of the inputs nothing is connected to: the default of the
+//! function, or the value set on the node instead.
+
+use crate::params::Param;
+
+use super::{AsSocketId, InputSocketId, NodeData, NodeKind, SocketKind, SpatialItem, Tree};
+
+impl NodeKind {
+    /// Value the input gets when nothing is connected to it, `None` if the
+    /// input is required.
+    pub fn input_default(&self, index: usize) -> Option<&Param> {
+        match self {
+            NodeKind::Function(function) => function.default(index),
+            _ => None,
+        }
+    }
+}
+
+impl NodeData {
+    /// Value the input gets when nothing is connected to it: the one set on
+    /// the node or the default. `None` if the input is required and there
+    /// is no value set.
+    pub fn input_value(&self, index: usize) -> Option<&Param> {
+        self.values
+            .get(index)
+            .and_then(Option::as_ref)
+            .or_else(|| self.kind.input_default(index))
+    }
+}
+
+impl Tree {
+    /// Sets the value the input of the function node gets when nothing is
+    /// connected to it, `None` brings back the default.
+    ///
+    /// Returns the value set before, or `None` if there is no such input or
+    /// the value is of the wrong type or not finite.
+    pub fn set_input_value(
+        &mut self,
+        input: InputSocketId,
+        value: Option<Param>,
+    ) -> Option<Option<Param>> {
+        let node = input.node();
+        let data = self.node_mut(node)?;
+        let index = data.socket_id(SocketKind::Input, input.id())?;
+        let ty = data.kind.inputs()[index];
+        if !matches!(data.kind, NodeKind::Function(_))
+            || value
+                .as_ref()
+                .is_some_and(|x| x.get_type() != ty || !x.is_finite())
+        {
+            return None;
+        }
+
+        if data.values.len() <= index {
+            data.values.resize(index + 1, None);
+        }
+        let old = std::mem::replace(&mut data.values[index], value);
+        while data.values.last() == Some(&None) {
+            data.values.pop();
+        }
+        // Labels show the value, so the node may get wider.
+        let old_rect = data.bound_rect();
+        data.layout();
+        let rect = data.bound_rect();
+        self.index.insert(SpatialItem::Node(node), rect);
+        self.update_wires_around(node, &[old_rect, rect]);
+        Some(old)
+    }
+
+    /// Value set on the input with [`Tree::set_input_value`].
+    pub fn input_value(&self, input: InputSocketId) -> Option<&Param> {
+        self.node(input.node())?
+            .values
+            .get(input.id())
+            .and_then(Option::as_ref)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{params::ParamType, tree::tests::function};
+
+    use super::*;
+
+    #[test]
+    fn input_values() {
+        let mut tree = Tree::new();
+        let lerp = function("std.lerp");
+        let node = tree.create_node(lerp, (0.0, 0.0).into());
+        let input = |index| (node, index, SocketKind::Input).into_input();
+        let data = tree.node(node).unwrap();
+        assert_eq!(data.input_value(0), None);
+        assert_eq!(data.input_value(2), Some(&Param::f64(0.5)));
+        assert!(data.sockets[2].optional && !data.sockets[0].optional);
+        let width = data.size.width;
+
+        assert_eq!(tree.set_input_value(input(0), Some(Param::i64(1))), None);
+        assert_eq!(tree.set_input_value(input(5), Some(Param::f64(1.0))), None);
+        let value = Some(Param::f64(12345.678));
+        assert_eq!(tree.set_input_value(input(0), value), Some(None));
+        let data = tree.node(node).unwrap();
+        assert_eq!(data.input_value(0), Some(&Param::f64(12345.678)));
+        assert!(data.size.width > width);
+
+        assert_eq!(
+            tree.set_input_value(input(0), None),
+            Some(Some(Param::f64(12345.678)))
+        );
+        assert_eq!(tree.input_value(input(0)), None);
+        assert_eq!(tree.node(node).unwrap().size.width, width);
+    }
+
+    #[test]
+    fn edited_values_survive_saving() {
+        let mut tree = Tree::new();
+        let lerp = function("std.lerp");
+        let node = tree.create_node(lerp, (0.0, 0.0).into());
+        let input = |index| (node, index, SocketKind::Input).into_input();
+
+        for text in &["NaN", "inf", "-inf"] {
+            assert_eq!(Param::parse(ParamType::f64, text), None);
+        }
+        assert_eq!(
+            tree.set_input_value(input(0), Some(Param::f64(f64::NAN))),
+            None
+        );
+
+        let value = Param::parse(ParamType::f64, "-2.5");
+        assert_eq!(tree.set_input_value(input(2), value), Some(None));
+        let loaded = Tree::load(&tree.save()).unwrap();
+        let (_, data) = loaded.nodes().next().unwrap();
+        assert_eq!(data.input_value(2), Some(&Param::f64(-2.5)));
+    }
+}
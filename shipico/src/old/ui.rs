@@ -2,16 +2,50 @@ use once_cell::unsync::Lazy;
 
 use crate::{
     canvas::Canvas,
-    input::{InputEvent, InputMouseEvent, Keys},
+    input::{input, InputEvent, InputMouseEvent, Keys},
     log,
-    tree::Tree,
+    math::{Point, Rect, Size, Vec2},
+    params::Param,
+    tree::{
+        AsSocketId, CastResult, Diagnostic, Edit, Executor, History, InputSocketId, NodeId,
+        NodeKind, Routing, Snapping, SocketId, SocketKind, Tree, GRID_STEPS,
+    },
     widget::Component,
     widget::Widget,
-    FloatingWindow, Settings,
+    Action, AlignmentGuides, Breadcrumbs, DiagnosticsPanel, FloatingWindow, PhantomConnection,
+    SelectionRect, Settings,
 };
 
 pub struct InternalUi {
+    /// Root tree, see [`InternalUi::current`] for the tree user is editing.
     pub tree: Tree,
+    /// Groups user entered, starting from the root tree.
+    path: Vec<NodeId>,
+    pub history: History,
+    action: Action,
+    /// Nodes moved by the current drag: the grabbed node first, then the
+    /// rest of the selection along with the contents of selected frames.
+    dragged: Vec<NodeId>,
+    /// Part of the mouse movement snapping held back, so the nodes leave
+    /// the snapped position once the mouse gets far enough.
+    snap_remainder: Vec2,
+    phantom_connection: Option<PhantomConnection>,
+    selection_rect: Option<SelectionRect>,
+    alignment_guides: Option<AlignmentGuides>,
+    /// Problems of the root tree, `None` while the diagnostics panel is hidden.
+    diagnostics: Option<Vec<Diagnostic>>,
+    /// Evaluates the tree after every edit, `None` until user asks to.
+    executor: Option<Executor>,
+    /// Nodes that failed during the last evaluation, they stay highlighted
+    /// even if the diagnostics panel is hidden.
+    failures: Vec<Diagnostic>,
+    /// Message explaining why the last action failed.
+    status: Option<String>,
+    animation: Option<LayoutAnimation>,
+    /// Whether [`InternalUi::on_frame`] is already scheduled.
+    frame_requested: bool,
+    _on_frame: Option<Closure<dyn FnMut(f64)>>,
+    #[allow(dead_code)]
     pub floating_window: FloatingWindow,
     pub canvas: Canvas,
     pub settings: Settings,
@@ -42,18 +76,35 @@ impl std::ops::Deref for Ui {
 impl std::ops::DerefMut for Ui {
     #[inline]
     fn deref_mut(&mut self) -> &mut Self::Target {
-        let mut ui = unsafe { (*_UI).as_mut().unwrap() };
+        let ui = unsafe { (*_UI).as_mut().unwrap() };
         ui._dirty = true;
         ui
     }
 }
 
 impl InternalUi {
+    const TREE_STORAGE_KEY: &'static str = "shipico.tree";
+
     fn new() -> InternalUi {
         let canvas = Canvas::new();
         let tree = Tree::new();
         let mut ui = InternalUi {
             tree,
+            path: Vec::new(),
+            history: Default::default(),
+            action: Default::default(),
+            dragged: Vec::new(),
+            snap_remainder: Vec2::ZERO,
+            phantom_connection: None,
+            selection_rect: None,
+            alignment_guides: None,
+            diagnostics: None,
+            executor: None,
+            failures: Vec::new(),
+            status: None,
+            animation: None,
+            frame_requested: false,
+            _on_frame: None,
             canvas,
             floating_window: Default::default(),
             settings: Default::default(),
@@ -64,28 +115,594 @@ impl InternalUi {
         ui
     }
 
+    /// Persists current tree to the browser local storage.
+    pub fn save(&self) {
+        let storage = match self.canvas.window.local_storage() {
+            Ok(Some(storage)) => storage,
+            _ => {
+                log!("local storage is not available, tree is not saved");
+                return;
+            }
+        };
+
+        if let Err(err) = storage.set_item(Self::TREE_STORAGE_KEY, &self.tree.save()) {
+            log!("failed to save tree: {:?}", err);
+        }
+    }
+
+    /// Restores tree saved by [`InternalUi::save`].
+    ///
+    /// Returns `false` if there is nothing to restore or saved tree is broken.
+    pub fn load(&mut self) -> bool {
+        let text = match self.canvas.window.local_storage() {
+            Ok(Some(storage)) => storage.get_item(Self::TREE_STORAGE_KEY).ok().flatten(),
+            _ => None,
+        };
+
+        match text.as_deref().map(Tree::load) {
+            Some(Ok(mut tree)) => {
+                tree.set_routing(self.settings.routing);
+                self.tree = tree;
+                self.path.clear();
+                self.history = History::new();
+                // Ids of the loaded nodes can match ids of the old ones.
+                if self.executor.is_some() {
+                    self.executor = Some(Executor::new());
+                }
+                true
+            }
+            Some(Err(err)) => {
+                log!("failed to load saved tree: {}", err);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Tree of the group user is in, or the root tree.
+    pub fn current(&self) -> &Tree {
+        self.tree.subtree(&self.path).unwrap_or(&self.tree)
+    }
+
+    pub fn current_mut(&mut self) -> &mut Tree {
+        self.tree
+            .subtree_mut(&self.path)
+            .expect("path always leads to an existing group")
+    }
+
+    /// Applies edit to the current tree, so it can be undone later.
+    pub fn execute(&mut self, edit: Edit) {
+        self.finish_animation();
+        let moves_only = edit.moves_only();
+        let edit = self
+            .path
+            .iter()
+            .rev()
+            .fold(edit, |edit, &group| Edit::InGroup {
+                group,
+                edit: Box::new(edit),
+            });
+        self.history.execute(&mut self.tree, edit);
+        // Dragging nodes runs an edit on every mouse move, values don't
+        // change though.
+        if !moves_only {
+            self.reevaluate();
+        }
+    }
+
+    /// Goes back to the deepest group that still exists, undo can remove
+    /// the groups user is in.
+    fn leave_removed_groups(&mut self) {
+        while self.tree.subtree(&self.path).is_none() {
+            self.path.pop();
+        }
+    }
+
+    fn breadcrumbs(&self) -> Breadcrumbs {
+        let mut names = vec!["root".to_string()];
+        let mut tree = &self.tree;
+        for &node in &self.path {
+            if let Some(group) = tree.group(node) {
+                names.push(group.name.clone());
+                tree = group.tree();
+            }
+        }
+        Breadcrumbs { names }
+    }
+
+    pub fn remove_selection(&mut self) {
+        let edits = self
+            .current()
+            .selection()
+            .iter()
+            .map(|&node| Edit::RemoveNode(node))
+            .collect();
+        self.execute(Edit::Batch(edits));
+    }
+
+    /// Serializes selected nodes, `None` if nothing is selected.
+    pub fn copy_selection(&self) -> Option<String> {
+        let selection = self.current().selection();
+        if selection.is_empty() {
+            return None;
+        }
+        Some(self.current().save_fragment(selection))
+    }
+
+    /// Pastes nodes copied by [`InternalUi::copy_selection`] around the screen position.
+    pub fn paste(&mut self, text: &str, position: Point) {
+        match Tree::load_fragment(text) {
+            Ok(fragment) => self.execute(Edit::Paste {
+                fragment: Box::new(fragment),
+                position,
+            }),
+            Err(err) => self.status = Some(format!("can't paste: {}", err)),
+        }
+    }
+
+    pub fn duplicate_selection(&mut self) {
+        const OFFSET: [f64; 2] = [30.0, 30.0];
+        let offset = self.current().canvas_vector(OFFSET.into());
+        self.execute(Edit::Duplicate {
+            nodes: self.current().selection().to_vec(),
+            offset,
+        });
+    }
+
+    /// Asks user for a text, `None` if the dialog is cancelled.
+    fn prompt(&self, message: &str, default: &str) -> Option<String> {
+        let text = self
+            .canvas
+            .window
+            .prompt_with_message_and_default(message, default)
+            .ok()
+            .flatten();
+        input().release_keys();
+        text
+    }
+
+    /// Frames selected nodes, or creates an empty frame at the screen position.
+    pub fn create_frame(&mut self, position: Point) {
+        const SIZE: Size = Size {
+            width: 400.0,
+            height: 300.0,
+        };
+        let rect = self
+            .current()
+            .frame_rect(self.current().selection())
+            .unwrap_or_else(|| Rect::from_center_size(position, SIZE));
+        if let Some(title) = self.prompt("Frame title", "frame") {
+            self.execute(Edit::CreateFrame { title, rect });
+        }
+    }
+
+    pub fn create_note(&mut self, position: Point) {
+        if let Some(text) = self.prompt("Note", "") {
+            self.execute(Edit::CreateNote { text, position });
+        }
+    }
+
+    /// Lets user change the frame title or the note text.
+    fn edit_text(&mut self, node: NodeId) {
+        let old = match self.current().text(node) {
+            Some(text) => text.to_string(),
+            None => return,
+        };
+        match self.prompt("Text", &old) {
+            Some(text) if text != old => self.execute(Edit::SetText { node, text }),
+            _ => {}
+        }
+    }
+
+    /// Lets user set the value of the unconnected input, empty text brings
+    /// back the default.
+    fn edit_input_value(&mut self, input: InputSocketId) {
+        let node = match self.current().node(input.node()) {
+            Some(node) if matches!(node.kind(), NodeKind::Function(_)) => node,
+            _ => return,
+        };
+        let ty = node.kind().inputs()[input.id()];
+        let name = node.kind().input_name(input.id());
+        let old = self.current().input_value(input).cloned();
+        let text = old.as_ref().map(|x| x.to_string()).unwrap_or_default();
+        let message = format!("Value of `{}`, empty for the default", name);
+        let value = match self.prompt(&message, &text) {
+            Some(text) if text.trim().is_empty() => None,
+            Some(text) => match Param::parse(ty, text.trim()) {
+                Some(value) => Some(value),
+                None => {
+                    self.status = Some(format!("`{}` is not `{}`", text, ty));
+                    return;
+                }
+            },
+            None => return,
+        };
+        if value != old {
+            self.execute(Edit::SetInputValue { input, value });
+        }
+    }
+
+    /// Switches between curved and orthogonal connections.
+    pub fn toggle_routing(&mut self) {
+        self.finish_animation();
+        self.settings.routing = match self.settings.routing {
+            Routing::Curved => Routing::Orthogonal,
+            Routing::Orthogonal => Routing::Curved,
+        };
+        self.tree.set_routing(self.settings.routing);
+    }
+
+    /// Shows or hides the list of the tree problems.
+    pub fn toggle_diagnostics(&mut self) {
+        self.diagnostics = match self.diagnostics {
+            Some(_) => None,
+            None => Some(Vec::new()),
+        };
+    }
+
+    /// Validates the tree again if the diagnostics panel is shown, and
+    /// highlights nodes of the current tree that have problems, or contain
+    /// nodes with problems.
+    fn update_diagnostics(&mut self) {
+        if self.diagnostics.is_some() {
+            let mut diagnostics = self.tree.validate();
+            diagnostics.extend(self.failures.iter().cloned());
+            self.diagnostics = Some(diagnostics);
+        }
+        let depth = self.path.len();
+        let highlights = self
+            .diagnostics
+            .as_ref()
+            .unwrap_or(&self.failures)
+            .iter()
+            .filter(|x| x.path.starts_with(&self.path))
+            .map(|x| (*x.path.get(depth).unwrap_or(&x.node), x.severity))
+            .collect();
+        self.current_mut().set_highlights(highlights);
+    }
+
+    fn diagnostics_panel(&self) -> Option<DiagnosticsPanel> {
+        let rows = self
+            .diagnostics
+            .as_ref()?
+            .iter()
+            .map(|x| {
+                let tree = self.tree.subtree(&x.path);
+                let name = tree
+                    .and_then(|tree| tree.node(x.node))
+                    .map_or("", |node| node.kind().name());
+                (x.severity, format!("{}: {}", name, x.problem))
+            })
+            .collect();
+        Some(DiagnosticsPanel { rows })
+    }
+
+    /// Diagnostic in the panel under the mouse.
+    fn diagnostic_at(&self, pos: Point) -> Option<&Diagnostic> {
+        let row = self.diagnostics_panel()?.row_at(pos)?;
+        self.diagnostics.as_ref()?.get(row)
+    }
+
+    /// Goes to the tree the node of the diagnostic is in and selects it.
+    fn show_diagnostic(&mut self, diagnostic: Diagnostic) {
+        if self.tree.subtree(&diagnostic.path).is_none() {
+            return;
+        }
+        self.path = diagnostic.path;
+        self.current_mut().clear_selection();
+        self.current_mut().select(diagnostic.node);
+    }
+
+    /// Starts evaluating the root tree after every edit, or stops it.
+    pub fn toggle_evaluation(&mut self) {
+        self.executor = match self.executor {
+            Some(_) => None,
+            None => Some(Executor::new()),
+        };
+        self.failures.clear();
+        self.reevaluate();
+    }
+
+    /// Computes the root tree again and reports how much was computed, or
+    /// how many errors keep the tree from being computed. Nodes that fail
+    /// are highlighted.
+    ///
+    /// Only the nodes affected by the edits since the last run are computed.
+    fn reevaluate(&mut self) {
+        let executor = match &mut self.executor {
+            Some(executor) => executor,
+            None => return,
+        };
+        let evaluation = match executor.run(&self.tree) {
+            Ok(evaluation) => evaluation,
+            Err(errors) => {
+                self.status = Some(format!("can't evaluate: {} errors", errors.len()));
+                // Panel user hid stays hidden, the status tells about the errors.
+                if self.diagnostics.is_some() {
+                    self.diagnostics = Some(errors);
+                }
+                self.failures.clear();
+                return;
+            }
+        };
+        self.failures = evaluation.failures().to_vec();
+        let counters = executor.counters();
+        self.status = Some(match counters.failed {
+            0 => format!(
+                "recomputed {} of {} nodes",
+                counters.computed,
+                counters.computed + counters.reused
+            ),
+            failed => format!("{} nodes failed, {} skipped", failed, counters.skipped),
+        });
+    }
+
+    /// Cycles the grid nodes snap to through the grid steps and off.
+    pub fn cycle_grid(&mut self) {
+        let snapping = &mut self.settings.snapping;
+        snapping.grid = match snapping.grid {
+            None => Some(GRID_STEPS[0]),
+            Some(step) => GRID_STEPS.iter().copied().find(|&x| x > step),
+        };
+        self.status = Some(match snapping.grid {
+            Some(step) => format!("grid: {}", step),
+            None => "grid: off".to_string(),
+        });
+    }
+
+    /// Lays out selected nodes, or the whole tree if nothing is selected,
+    /// and animates nodes to their new positions.
+    pub fn tidy_up(&mut self) {
+        let tree = self.current();
+        let nodes = match tree.selection() {
+            [] => tree.nodes().map(|(id, _)| id).collect(),
+            selection => selection.to_vec(),
+        };
+        let positions = tree.layout(&nodes);
+        let moves = positions
+            .iter()
+            .map(|&(node, to)| (node, tree.node(node).unwrap().position(), to))
+            .collect::<Vec<_>>();
+
+        // History gets the final positions, animation then moves nodes
+        // from where they were.
+        self.execute(Edit::Arrange(positions));
+        for &(node, from, to) in &moves {
+            self.current_mut().move_node(node, from - to);
+        }
+        self.animation = Some(LayoutAnimation {
+            path: self.path.clone(),
+            moves,
+            start: None,
+        });
+        self.request_frame();
+    }
+
+    /// Puts animated nodes to their final positions right away.
+    fn finish_animation(&mut self) {
+        if let Some(animation) = self.animation.take() {
+            animation.apply(&mut self.tree, 1.0);
+        }
+    }
+
+    /// Schedules [`InternalUi::on_frame`] to run before the next repaint.
+    fn request_frame(&mut self) {
+        if self.frame_requested {
+            return;
+        }
+        let on_frame = self._on_frame.get_or_insert_with(|| {
+            Closure::wrap(Box::new(|time: f64| ui().on_frame(time)) as Box<dyn FnMut(f64)>)
+        });
+        self.frame_requested = self
+            .canvas
+            .window
+            .request_animation_frame(on_frame.as_ref().unchecked_ref())
+            .is_ok();
+    }
+
+    /// Advances the animation, `time` is in milliseconds.
+    fn on_frame(&mut self, time: f64) {
+        self.frame_requested = false;
+        let animation = match &mut self.animation {
+            Some(animation) => animation,
+            None => return,
+        };
+
+        let start = *animation.start.get_or_insert(time);
+        let progress = ((time - start) / LayoutAnimation::DURATION).min(1.0);
+        animation.apply(&mut self.tree, progress);
+        if progress < 1.0 {
+            self.request_frame();
+        } else {
+            self.animation = None;
+        }
+        self.redraw();
+    }
+
     pub fn update(&mut self, event: InputEvent) {
         let pressed = |keys: Keys| !event.keys_lately.contains(keys) && event.keys.contains(keys);
         let pressing = |keys: Keys| event.keys_lately.contains(keys) && event.keys.contains(keys);
         let released = |keys: Keys| event.keys_lately.contains(keys) && !event.keys.contains(keys);
         let no_keys = || event.keys.is_empty();
         let down = |keys: Keys| pressing(keys) || pressed(keys);
+        // Alt bypasses snapping while dragging nodes.
+        let snap = !down(Keys::ALT);
         // let not = |keys: Keys| !event.keys.contains(keys);
 
         match event.mouse_event {
-            InputMouseEvent::Click(_) if no_keys() => {
-                // Click only
-                log!("click!");
+            InputMouseEvent::Click(pos) if no_keys() && self.diagnostic_at(pos).is_some() => {
+                let diagnostic = self.diagnostic_at(pos).unwrap().clone();
+                self.show_diagnostic(diagnostic);
+            }
+            InputMouseEvent::Click(pos) if no_keys() => match self.breadcrumbs().crumb_at(pos) {
+                Some(depth) => self.path.truncate(depth),
+                None => {
+                    self.current_mut().clear_selection();
+                    if let CastResult::Node(node) = self.current().point_cast(pos) {
+                        self.current_mut().select(node);
+                    }
+                }
+            },
+            InputMouseEvent::Click(pos) if down(Keys::SHIFT) => {
+                if let CastResult::Node(node) = self.current().point_cast(pos) {
+                    self.current_mut().toggle_selection(node);
+                }
+            }
+            InputMouseEvent::Click(pos) if pressing(Keys::DELETE) => {
+                if let CastResult::Node(node) = self.current().point_cast(pos) {
+                    self.execute(Edit::RemoveNode(node));
+                }
+            }
+            InputMouseEvent::DoubleClick(pos) => match self.current().point_cast(pos) {
+                CastResult::Node(node) if self.current().group(node).is_some() => {
+                    self.path.push(node);
+                }
+                CastResult::Node(node) if self.current().text(node).is_some() => {
+                    self.edit_text(node);
+                }
+                CastResult::Socket(socket, _)
+                    if socket.kind() == SocketKind::Input
+                        && self
+                            .current()
+                            .connected_output(socket.into_input())
+                            .is_none() =>
+                {
+                    self.edit_input_value(socket.into_input());
+                }
+                CastResult::Connection(input) => {
+                    self.execute(Edit::Reroute {
+                        input,
+                        position: pos,
+                    });
+                }
+                _ => return,
+            },
+            InputMouseEvent::StartDrag(pos, delta) if no_keys() || event.keys == Keys::ALT => {
+                self.status = None;
+                self.action = match self.current().point_cast(pos) {
+                    CastResult::Node(node) => {
+                        if !self.current().is_selected(node) {
+                            self.current_mut().clear_selection();
+                            self.current_mut().select(node);
+                        }
+                        let tree = self.current();
+                        let mut dragged = vec![node];
+                        for &node in tree.selection() {
+                            if !dragged.contains(&node) {
+                                dragged.push(node);
+                            }
+                        }
+                        for &frame in tree.selection() {
+                            for node in tree.frame_contents(frame) {
+                                if !dragged.contains(&node) {
+                                    dragged.push(node);
+                                }
+                            }
+                        }
+                        self.dragged = dragged;
+                        self.snap_remainder = Vec2::ZERO;
+                        // The whole drag is undone at once.
+                        self.history.begin_gesture();
+                        Action::DragSelection
+                    }
+                    CastResult::Socket(socket, socket_pos) => {
+                        self.current_mut().set_pending_connection(Some(socket));
+                        self.phantom_connection = Some(PhantomConnection {
+                            from: socket_pos,
+                            to: pos + delta,
+                            kind: socket.kind(),
+                        });
+                        Action::DragSocket(socket)
+                    }
+                    _ => Action::DragScreen,
+                };
+                self.drag(pos + delta, delta, snap);
+            }
+            InputMouseEvent::StartDrag(pos, delta) if down(Keys::SHIFT) => {
+                self.selection_rect = Some(SelectionRect {
+                    from: pos,
+                    to: pos + delta,
+                });
+                self.action = Action::SelectRect;
+            }
+            InputMouseEvent::EndDrag(pos) => {
+                match self.action {
+                    Action::DragSocket(from) => self.drop_socket(from, pos),
+                    Action::SelectRect => {
+                        if let Some(selection_rect) = self.selection_rect.take() {
+                            for node in self.current().rect_cast(selection_rect.rect()) {
+                                self.current_mut().select(node);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                self.history.end_gesture();
+                self.action = Action::None;
+                self.phantom_connection = None;
+                self.alignment_guides = None;
+                self.current_mut().set_pending_connection(None);
+            }
+            InputMouseEvent::Drag(pos, delta)
+                if no_keys() || down(Keys::SHIFT) || down(Keys::ALT) =>
+            {
+                self.drag(pos, delta, snap);
+            }
+            _ if down(Keys::CTRL) && down(Keys::SHIFT) && pressed(Keys::UNDO) => {
+                self.finish_animation();
+                if !self.history.redo(&mut self.tree) {
+                    return;
+                }
+                self.leave_removed_groups();
+                self.reevaluate();
+            }
+            _ if down(Keys::CTRL) && pressed(Keys::UNDO) => {
+                self.finish_animation();
+                if !self.history.undo(&mut self.tree) {
+                    return;
+                }
+                self.leave_removed_groups();
+                self.reevaluate();
+            }
+            _ if pressed(Keys::REMOVE) => {
+                self.remove_selection();
+            }
+            _ if down(Keys::SHIFT) && pressed(Keys::DUPLICATE) => {
+                self.duplicate_selection();
+            }
+            _ if down(Keys::SHIFT) && pressed(Keys::TIDY) => {
+                self.tidy_up();
             }
-            InputMouseEvent::Click(_) if pressing(Keys::DELETE) => {
-                log!("click delete!");
+            _ if down(Keys::SHIFT) && pressed(Keys::ROUTING) => {
+                self.toggle_routing();
             }
-            InputMouseEvent::StartDrag(pos, delta) if no_keys() => {
-                self.tree.drag(delta);
+            _ if down(Keys::SHIFT) && pressed(Keys::EVALUATE) => {
+                self.toggle_evaluation();
             }
-            InputMouseEvent::EndDrag(_) if no_keys() => {}
-            InputMouseEvent::Drag(pos, delta) if no_keys() => {
-                self.tree.drag(delta);
+            _ if down(Keys::SHIFT) && pressed(Keys::VALIDATE) => {
+                self.toggle_diagnostics();
+            }
+            _ if down(Keys::SHIFT) && pressed(Keys::SNAP) => {
+                self.cycle_grid();
+            }
+            _ if down(Keys::SHIFT) && pressed(Keys::FRAME) => {
+                self.create_frame(input().mouse_pos());
+            }
+            _ if down(Keys::SHIFT) && pressed(Keys::NOTE) => {
+                self.create_note(input().mouse_pos());
+            }
+            _ if down(Keys::CTRL) && pressed(Keys::GROUP) => {
+                let nodes = self.current().selection().to_vec();
+                self.execute(Edit::Collapse(nodes));
+            }
+            _ if pressed(Keys::BACK) => {
+                if self.path.pop().is_none() {
+                    return;
+                }
+            }
+            _ if down(Keys::CTRL) && pressed(Keys::SAVE) => {
+                self.save();
+                return;
             }
             _ if pressed(Keys::MENU) => {
                 log!("menu!");
@@ -113,7 +730,7 @@ impl InternalUi {
                 if down(Keys::ARROW_LEFT) {
                     x += 1.0 * SPEED;
                 }
-                self.tree.drag([x, y].into())
+                self.current_mut().drag([x, y].into())
             }
             _ => {
                 return;
@@ -123,11 +740,77 @@ impl InternalUi {
         self.redraw();
     }
 
+    /// Handles mouse movement during the drag, `snap` is `false` while the
+    /// snapping is bypassed.
+    fn drag(&mut self, pos: Point, delta: Vec2, snap: bool) {
+        match self.action {
+            Action::DragSelection => {
+                let tree = self.current();
+                let desired = self.snap_remainder + tree.canvas_vector(delta);
+                let snapping = if snap {
+                    self.settings.snapping
+                } else {
+                    Snapping::NONE
+                };
+                let (offset, guides) = tree.snap(&self.dragged, self.dragged[0], desired, snapping);
+                let lines = guides
+                    .into_iter()
+                    .map(|x| (tree.canvas_to_screen(x.start), tree.canvas_to_screen(x.end)))
+                    .collect();
+                self.alignment_guides = Some(AlignmentGuides { lines });
+                self.snap_remainder = desired - offset;
+                let nodes = self.dragged.clone();
+                self.execute(Edit::MoveNodes { nodes, offset });
+            }
+            Action::DragSocket(_) => {
+                if let Some(phantom_connection) = &mut self.phantom_connection {
+                    phantom_connection.to = pos;
+                }
+            }
+            Action::SelectRect => {
+                if let Some(selection_rect) = &mut self.selection_rect {
+                    selection_rect.to = pos;
+                }
+            }
+            _ => self.current_mut().drag(delta),
+        }
+    }
+
+    /// Connects dragged socket to the socket under the mouse, if there is one.
+    fn drop_socket(&mut self, from: SocketId, pos: Point) {
+        let to = match self.current().point_cast(pos) {
+            CastResult::Socket(to, _) => to,
+            _ => return,
+        };
+
+        match self.current().can_connect(from, to) {
+            Ok(link) => self.execute(Edit::CreateConnection {
+                input: link.input,
+                output: link.output,
+            }),
+            Err(err) => self.status = Some(err.to_string()),
+        }
+    }
+
     pub fn redraw(&mut self) {
         log!("REDRAW!");
 
         self.canvas.reset();
-        self.tree.build().draw(&mut self.canvas);
+        self.update_diagnostics();
+        self.current().build().draw(&mut self.canvas);
+        self.breadcrumbs().draw(&mut self.canvas);
+        if let Some(diagnostics_panel) = self.diagnostics_panel() {
+            diagnostics_panel.draw(&mut self.canvas);
+        }
+        if let Some(phantom_connection) = &self.phantom_connection {
+            phantom_connection.draw(&mut self.canvas);
+        }
+        if let Some(selection_rect) = &self.selection_rect {
+            selection_rect.draw(&mut self.canvas);
+        }
+        if let Some(alignment_guides) = &self.alignment_guides {
+            alignment_guides.draw(&mut self.canvas);
+        }
         self.draw_debug();
 
         // if let Some(phantom_connection) = &self.state.phantom_connection {
@@ -153,9 +836,12 @@ impl InternalUi {
             context.fill_text(&text, 50.0, last_y).unwrap();
             last_y += 40.0;
         };
-        fill_text(format!("Zoom: {:1.3?}", self.tree.z()));
-        fill_text(format!("X: {:4.3?}", self.tree.x()));
-        fill_text(format!("Y: {:4.3?}", self.tree.y()));
+        fill_text(format!("Zoom: {:1.3?}", self.current().z()));
+        fill_text(format!("X: {:4.3?}", self.current().x()));
+        fill_text(format!("Y: {:4.3?}", self.current().y()));
+        if let Some(status) = &self.status {
+            fill_text(status.clone());
+        }
         // fill_text(format!("Mouse down: {}", self.state.mouse_down));
         // fill_text(format!("Alt: {}", self.state.delete_button));
         // fill_text(format!(
@@ -166,7 +852,7 @@ impl InternalUi {
         //     "Mouse delta: x: {:4.3?}, y: {:4.3?}",
         //     self.state.mouse_delta.x, self.state.mouse_delta.y
         // ));
-        // let canvas_point = self.tree.screen_to_canvas(self.state.mouse_pos);
+        // let canvas_point = self.current().screen_to_canvas(self.state.mouse_pos);
         // fill_text(format!(
         //     "Canvas mouse: x: {:4.3?}, y: {:4.3?}",
         //     canvas_point.x, canvas_point.y
@@ -174,6 +860,36 @@ impl InternalUi {
     }
 }
 
+/// Nodes moving to the positions found by [`Tree::layout`].
+struct LayoutAnimation {
+    /// Path to the tree nodes are in, user can leave it during the animation.
+    path: Vec<NodeId>,
+    /// Nodes with their start and end positions in canvas space.
+    moves: Vec<(NodeId, Point, Point)>,
+    /// Time of the first frame.
+    start: Option<f64>,
+}
+
+impl LayoutAnimation {
+    /// In milliseconds.
+    const DURATION: f64 = 300.0;
+
+    /// Moves nodes to where they are at `progress` from 0 to 1.
+    fn apply(&self, root: &mut Tree, progress: f64) {
+        let tree = match root.subtree_mut(&self.path) {
+            Some(tree) => tree,
+            None => return,
+        };
+        // Smoothstep, so nodes speed up and slow down gently.
+        let t = progress * progress * (3.0 - 2.0 * progress);
+        for &(node, from, to) in &self.moves {
+            if let Some(position) = tree.node(node).map(|x| x.position()) {
+                tree.move_node(node, (from + (to - from) * t) - position);
+            }
+        }
+    }
+}
+
 macro_rules! hooks {
     ($($hook: ident($($param_type: tt),*)),*,) => {
         hooks!($($hook($($param_type),*)),*);
@@ -212,4 +928,5 @@ hooks!(
     keyup(KeyboardEvent),
     wheel(WheelEvent),
     contextmenu(MouseEvent),
+    dblclick(MouseEvent),
 );
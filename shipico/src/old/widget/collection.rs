@@ -66,6 +66,7 @@ where
     }
 }
 
+#[allow(dead_code)]
 pub struct StackBuilder<L, O>
 where
     L: Fn(usize) -> Option<O>,
@@ -94,6 +95,7 @@ where
     L: Fn(usize) -> Option<O>,
     O: Widget,
 {
+    #[allow(dead_code)]
     #[inline]
     pub fn of(lambda: L) -> StackBuilder<L, O> {
         StackBuilder { lambda }
@@ -3,11 +3,9 @@
 //! Common is not the best name for it, but i don't know
 //! what is better
 
-use web_sys::DomMatrix;
-
 use crate::{
     canvas::Canvas,
-    math::{Matrix, Vec2},
+    math::{Matrix, Point, Vec2},
 };
 
 use super::Widget;
@@ -146,6 +144,31 @@ where
     }
 }
 
+// ----------------------------------------------------------------
+// Text
+// ----------------------------------------------------------------
+/// Filled text, every line of it goes below the previous one.
+pub struct Text {
+    pub text: String,
+    /// Top left corner of the first line, top right for the right aligned text.
+    pub position: Point,
+    /// Font size, which is also the line height.
+    pub size: f64,
+}
+
+impl Widget for Text {
+    #[inline]
+    fn draw(&self, canvas: &mut Canvas) {
+        for (i, line) in self.text.lines().enumerate() {
+            canvas.fill_text(
+                line,
+                self.position + [0.0, self.size * (i + 1) as f64],
+                self.size,
+            );
+        }
+    }
+}
+
 pub struct Inspect<T, F>
 where
     T: Widget,
@@ -1,6 +1,6 @@
 use crate::{
     canvas::Canvas,
-    math::{Ellipse, Line, Rect, RoundedRect},
+    math::{BezierSegment, Ellipse, Line, Point, Rect, RoundedRect},
 };
 
 use super::Widget;
@@ -19,6 +19,46 @@ impl Shape for Line {
     }
 }
 
+/// Cubic curve from the point.
+impl Shape for (Point, BezierSegment) {
+    #[inline]
+    fn outline(&self, canvas: &mut Canvas) {
+        let (start, segment) = self;
+        canvas.move_to(*start);
+        canvas.bezier_curve_to(segment.p1, segment.p2, segment.p3);
+    }
+    #[inline]
+    fn bound_rect(&self) -> Rect {
+        self.1.bound_rect(self.0)
+    }
+    #[inline]
+    fn is_closed(&self) -> bool {
+        false
+    }
+}
+
+/// Open polyline through the points.
+impl Shape for Vec<Point> {
+    #[inline]
+    fn outline(&self, canvas: &mut Canvas) {
+        if let Some((first, rest)) = self.split_first() {
+            canvas.move_to(*first);
+            rest.iter().for_each(|&x| canvas.line_to(x));
+        }
+    }
+    #[inline]
+    fn bound_rect(&self) -> Rect {
+        self.iter()
+            .map(|&x| Rect::from_points(x, x))
+            .reduce(|a, b| a.combined_with(b))
+            .unwrap_or_default()
+    }
+    #[inline]
+    fn is_closed(&self) -> bool {
+        false
+    }
+}
+
 impl Shape for RoundedRect {
     fn outline(&self, canvas: &mut Canvas) {
         let left = self.rect.left;
@@ -142,6 +182,12 @@ impl Shape for Ellipse {
 pub trait Shape {
     fn outline(&self, canvas: &mut Canvas);
     fn bound_rect(&self) -> Rect;
+
+    /// Closed shapes get a line from the end of the outline back to its start.
+    #[inline]
+    fn is_closed(&self) -> bool {
+        true
+    }
 }
 
 impl<T> Widget for T
@@ -154,6 +200,8 @@ where
         if canvas.is_rect_in_screen(self.bound_rect()) {
             self.outline(canvas)
         }
-        canvas.close_path();
+        if self.is_closed() {
+            canvas.close_path();
+        }
     }
 }
@@ -79,6 +79,7 @@ styles! {
     line_width(width: f64);
 
     font(font: &'static str);
+    text_align(align: &'static str);
 
     line_cap(cap: &'static str);
 }